@@ -0,0 +1,697 @@
+//! Typed model of the address books written after deployment, such as
+//! `config/lending-market-mainnet-product.json`.
+//!
+//! An address book lists the accounts of each deployed reserve, keyed by liquidity mint, and
+//! the bridge, AMM and farm accounts of LP reserves. Names are only set on some entries,
+//! [MarketAddressBook::with_names](struct.MarketAddressBook.html#method.with_names) fills in
+//! the others from the market config.
+
+use super::{
+    deserialize_optional_pubkey, deserialize_pubkey, deserialize_pubkey_map,
+    keypair::{EmbeddedSecret, SecretKeypair, LENDING_MARKET_KEYPAIR_ENV},
+    ConfigError, MarketConfig,
+};
+use crate::instruction::{deposit_reserve_liquidity, redeem_reserve_collateral};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// Deployed lending market accounts
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct MarketAddressBook {
+    /// Keypair of the lending market account, older address books embed it
+    #[serde(
+        rename = "account",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_keypair_bytes"
+    )]
+    embedded_keypair: Option<SecretKeypair>,
+    /// Lending market account
+    #[serde(rename = "account_pubkey", with = "pubkey_string")]
+    pub lending_market: Pubkey,
+    /// Reserves by liquidity mint
+    #[serde(with = "pubkey_map_string")]
+    pub reserves: BTreeMap<Pubkey, ReserveAddresses>,
+}
+
+impl MarketAddressBook {
+    /// Parse an address book, fails if it embeds a secret key
+    pub fn from_json(json: &str) -> Result<Self, ConfigError> {
+        Self::from_json_with(json, EmbeddedSecret::Refuse)
+    }
+
+    /// Parse an address book, failing if it embeds a secret key unless allowed
+    pub fn from_json_with(
+        json: &str,
+        embedded_secret: EmbeddedSecret,
+    ) -> Result<Self, ConfigError> {
+        let address_book: Self = serde_json::from_str(json)?;
+        if let Some(keypair) = &address_book.embedded_keypair {
+            if embedded_secret == EmbeddedSecret::Refuse {
+                return Err(ConfigError::EmbeddedSecret);
+            }
+            keypair.check_pubkey(&address_book.lending_market)?;
+        }
+        Ok(address_book)
+    }
+
+    /// Read and parse an address book file, fails if it embeds a secret key
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::load_with(path, EmbeddedSecret::Refuse)
+    }
+
+    /// Read and parse an address book file, failing if it embeds a secret key unless allowed
+    pub fn load_with(
+        path: impl AsRef<Path>,
+        embedded_secret: EmbeddedSecret,
+    ) -> Result<Self, ConfigError> {
+        Self::from_json_with(&fs::read_to_string(path)?, embedded_secret)
+    }
+
+    /// Serialize the address book, failing if it embeds a secret key unless allowed
+    pub fn to_json(&self, embedded_secret: EmbeddedSecret) -> Result<String, ConfigError> {
+        if self.embedded_keypair.is_some() && embedded_secret == EmbeddedSecret::Refuse {
+            return Err(ConfigError::EmbeddedSecret);
+        }
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Write the address book file, failing if it embeds a secret key unless allowed
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        embedded_secret: EmbeddedSecret,
+    ) -> Result<(), ConfigError> {
+        Ok(fs::write(path, self.to_json(embedded_secret)?)?)
+    }
+
+    /// True if the address book embeds the lending market keypair
+    pub fn has_embedded_secret(&self) -> bool {
+        self.embedded_keypair.is_some()
+    }
+
+    /// Remove the embedded lending market keypair
+    pub fn remove_embedded_secret(&mut self) {
+        self.embedded_keypair = None;
+    }
+
+    /// Lending market keypair, read from `path` or else from the file at the path held by
+    /// the `LENDING_MARKET_KEYPAIR` environment variable, or else the keypair embedded in
+    /// the address book when loaded with `EmbeddedSecret::Allow`
+    pub fn lending_market_keypair(
+        &self,
+        path: Option<&Path>,
+    ) -> Result<SecretKeypair, ConfigError> {
+        let keypair = match (path, &self.embedded_keypair) {
+            (Some(path), _) => SecretKeypair::read(path)?,
+            (None, Some(keypair)) if std::env::var_os(LENDING_MARKET_KEYPAIR_ENV).is_none() => {
+                keypair.clone()
+            }
+            (None, _) => SecretKeypair::read_from_env(LENDING_MARKET_KEYPAIR_ENV)?,
+        };
+        keypair.check_pubkey(&self.lending_market)?;
+        Ok(keypair)
+    }
+
+    /// Set the names missing from the address book to the names of the market config
+    pub fn with_names(mut self, config: &MarketConfig) -> Self {
+        for (liquidity_mint, reserve) in self.reserves.iter_mut() {
+            if reserve.name.is_none() {
+                reserve.name = config
+                    .reserves
+                    .get(liquidity_mint)
+                    .map(|entry| entry.name.clone());
+            }
+        }
+        self
+    }
+
+    /// Reserve by name, e.g. "USDC"
+    pub fn reserve_by_symbol(&self, symbol: &str) -> Result<&ReserveAddresses, ConfigError> {
+        self.reserves
+            .values()
+            .find(|reserve| reserve.name.as_deref() == Some(symbol))
+            .ok_or_else(|| ConfigError::UnknownReserve(symbol.to_string()))
+    }
+
+    /// Accounts of a reserve by name
+    pub fn reserve_accounts(&self, symbol: &str) -> Result<ReserveAccounts, ConfigError> {
+        self.reserve_by_symbol(symbol)?.accounts()
+    }
+
+    /// Creates a 'DepositReserveLiquidity' instruction for a reserve by name
+    pub fn deposit_reserve_liquidity(
+        &self,
+        program_id: Pubkey,
+        symbol: &str,
+        liquidity_amount: u64,
+        source_liquidity_pubkey: Pubkey,
+        destination_collateral_pubkey: Pubkey,
+        user_transfer_authority_pubkey: Pubkey,
+    ) -> Result<Instruction, ConfigError> {
+        Ok(self.reserve_accounts(symbol)?.deposit_reserve_liquidity(
+            program_id,
+            liquidity_amount,
+            source_liquidity_pubkey,
+            destination_collateral_pubkey,
+            user_transfer_authority_pubkey,
+        ))
+    }
+
+    /// Creates a 'RedeemReserveCollateral' instruction for a reserve by name
+    pub fn redeem_reserve_collateral(
+        &self,
+        program_id: Pubkey,
+        symbol: &str,
+        collateral_amount: u64,
+        source_collateral_pubkey: Pubkey,
+        destination_liquidity_pubkey: Pubkey,
+        user_transfer_authority_pubkey: Pubkey,
+    ) -> Result<Instruction, ConfigError> {
+        Ok(self.reserve_accounts(symbol)?.redeem_reserve_collateral(
+            program_id,
+            collateral_amount,
+            source_collateral_pubkey,
+            destination_liquidity_pubkey,
+            user_transfer_authority_pubkey,
+        ))
+    }
+}
+
+/// Accounts of a deployed reserve, as listed in the address book
+///
+/// Only the reserve account is always listed, the reserve accounts of some LP reserves are
+/// missing from older address books.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ReserveAddresses {
+    /// Display name, e.g. "USDC" or "SOL-USDC" for LP tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Reserve account
+    #[serde(rename = "reserveId", with = "pubkey_string")]
+    pub reserve: Pubkey,
+    /// Reserve liquidity SPL Token mint
+    #[serde(
+        rename = "liquidityTokenID",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub liquidity_mint: Option<Pubkey>,
+    /// Reserve liquidity supply SPL Token account
+    #[serde(
+        rename = "liquiditySupplyID",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub liquidity_supply: Option<Pubkey>,
+    /// Reserve liquidity fee receiver
+    #[serde(
+        rename = "liquidityFeeReceiverID",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub liquidity_fee_receiver: Option<Pubkey>,
+    /// Reserve collateral SPL Token mint
+    #[serde(
+        rename = "collateralTokenID",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub collateral_mint: Option<Pubkey>,
+    /// Reserve collateral supply SPL Token account
+    #[serde(
+        rename = "collateralSupplyID",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub collateral_supply: Option<Pubkey>,
+    /// Lending market account
+    #[serde(
+        rename = "lendingMarketID",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub lending_market: Option<Pubkey>,
+    /// Lending market authority (PDA)
+    #[serde(
+        rename = "lendingMarketAuthority",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub lending_market_authority: Option<Pubkey>,
+    /// Lending market owner
+    #[serde(
+        rename = "lendingMarketOwnerAccount",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub lending_market_owner: Option<Pubkey>,
+    /// Bridge program accounts of an LP reserve
+    #[serde(flatten)]
+    pub lp: LpAddresses,
+}
+
+/// Bridge, AMM and farm accounts of an LP reserve, unset for other reserves
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct LpAddresses {
+    /// Version of the AMM and farm
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u8>,
+    /// BridgePool account of bridge program
+    #[serde(
+        rename = "bridgePoolID",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub bridge_pool: Option<Pubkey>,
+    /// AMM account
+    #[serde(
+        rename = "ammID",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub amm: Option<Pubkey>,
+    /// LP token mint
+    #[serde(
+        rename = "lpMint",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub lp_mint: Option<Pubkey>,
+    /// Price account of the AMM coin
+    #[serde(
+        rename = "coinMintPrice",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub coin_mint_price: Option<Pubkey>,
+    /// Price account of the AMM pc
+    #[serde(
+        rename = "pcMintPrice",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub pc_mint_price: Option<Pubkey>,
+    /// AMM open orders account
+    #[serde(
+        rename = "ammOpenOrders",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub amm_open_orders: Option<Pubkey>,
+    /// AMM coin supply SPL Token account
+    #[serde(
+        rename = "ammCoinMintSupply",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub amm_coin_mint_supply: Option<Pubkey>,
+    /// AMM pc supply SPL Token account
+    #[serde(
+        rename = "ammPcMintSupply",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub amm_pc_mint_supply: Option<Pubkey>,
+    /// Farm pool account
+    #[serde(
+        rename = "farmPool",
+        alias = "farmPoolID",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub farm_pool: Option<Pubkey>,
+    /// Farm program id
+    #[serde(
+        rename = "farmPoolProgramId",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub farm_pool_program_id: Option<Pubkey>,
+    /// Farm pool authority
+    #[serde(
+        rename = "farmPoolAuthority",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub farm_pool_authority: Option<Pubkey>,
+    /// Farm pool LP supply SPL Token account
+    #[serde(
+        rename = "farmPoolLpSupply",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub farm_pool_lp_supply: Option<Pubkey>,
+    /// Reward token account of the bridge pool
+    #[serde(
+        rename = "farmRewardTokenAccount",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub farm_reward_token_account: Option<Pubkey>,
+    /// Farm reward vault
+    #[serde(
+        rename = "farmRewardVault",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub farm_reward_vault: Option<Pubkey>,
+    /// Second reward token account of the bridge pool, for farms with two rewards
+    #[serde(
+        rename = "farmRewardTokenAccountB",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub farm_reward_token_account_b: Option<Pubkey>,
+    /// Second farm reward vault, for farms with two rewards
+    #[serde(
+        rename = "farmRewardVaultB",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_pubkey_string"
+    )]
+    pub farm_reward_vault_b: Option<Pubkey>,
+}
+
+/// Accounts of a deployed reserve used by the reserve instructions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReserveAccounts {
+    /// Reserve account
+    pub reserve: Pubkey,
+    /// Reserve liquidity SPL Token mint
+    pub liquidity_mint: Pubkey,
+    /// Reserve liquidity supply SPL Token account
+    pub liquidity_supply: Pubkey,
+    /// Reserve liquidity fee receiver
+    pub liquidity_fee_receiver: Pubkey,
+    /// Reserve collateral SPL Token mint
+    pub collateral_mint: Pubkey,
+    /// Reserve collateral supply SPL Token account
+    pub collateral_supply: Pubkey,
+    /// Lending market account
+    pub lending_market: Pubkey,
+    /// Lending market authority (PDA)
+    pub lending_market_authority: Pubkey,
+}
+
+impl ReserveAddresses {
+    /// Reserve accounts, fails if the address book does not list all of them
+    pub fn accounts(&self) -> Result<ReserveAccounts, ConfigError> {
+        let missing = |field: &str| ConfigError::InvalidReserve {
+            name: self
+                .name
+                .clone()
+                .unwrap_or_else(|| self.reserve.to_string()),
+            reason: format!("{} not in address book", field),
+        };
+        Ok(ReserveAccounts {
+            reserve: self.reserve,
+            liquidity_mint: self
+                .liquidity_mint
+                .ok_or_else(|| missing("liquidityTokenID"))?,
+            liquidity_supply: self
+                .liquidity_supply
+                .ok_or_else(|| missing("liquiditySupplyID"))?,
+            liquidity_fee_receiver: self
+                .liquidity_fee_receiver
+                .ok_or_else(|| missing("liquidityFeeReceiverID"))?,
+            collateral_mint: self
+                .collateral_mint
+                .ok_or_else(|| missing("collateralTokenID"))?,
+            collateral_supply: self
+                .collateral_supply
+                .ok_or_else(|| missing("collateralSupplyID"))?,
+            lending_market: self
+                .lending_market
+                .ok_or_else(|| missing("lendingMarketID"))?,
+            lending_market_authority: self
+                .lending_market_authority
+                .ok_or_else(|| missing("lendingMarketAuthority"))?,
+        })
+    }
+}
+
+impl ReserveAccounts {
+    /// Creates a 'DepositReserveLiquidity' instruction.
+    pub fn deposit_reserve_liquidity(
+        &self,
+        program_id: Pubkey,
+        liquidity_amount: u64,
+        source_liquidity_pubkey: Pubkey,
+        destination_collateral_pubkey: Pubkey,
+        user_transfer_authority_pubkey: Pubkey,
+    ) -> Instruction {
+        deposit_reserve_liquidity(
+            program_id,
+            liquidity_amount,
+            source_liquidity_pubkey,
+            destination_collateral_pubkey,
+            self.reserve,
+            self.collateral_mint,
+            self.liquidity_supply,
+            self.lending_market,
+            self.lending_market_authority,
+            user_transfer_authority_pubkey,
+        )
+    }
+
+    /// Creates a 'RedeemReserveCollateral' instruction.
+    pub fn redeem_reserve_collateral(
+        &self,
+        program_id: Pubkey,
+        collateral_amount: u64,
+        source_collateral_pubkey: Pubkey,
+        destination_liquidity_pubkey: Pubkey,
+        user_transfer_authority_pubkey: Pubkey,
+    ) -> Instruction {
+        redeem_reserve_collateral(
+            program_id,
+            collateral_amount,
+            source_collateral_pubkey,
+            destination_liquidity_pubkey,
+            self.reserve,
+            self.collateral_mint,
+            self.liquidity_supply,
+            self.lending_market,
+            self.lending_market_authority,
+            user_transfer_authority_pubkey,
+        )
+    }
+}
+
+mod pubkey_string {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(pubkey)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        deserialize_pubkey(deserializer)
+    }
+}
+
+mod optional_pubkey_string {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        pubkey: &Option<Pubkey>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        pubkey
+            .map(|pubkey| pubkey.to_string())
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Pubkey>, D::Error> {
+        deserialize_optional_pubkey(deserializer)
+    }
+}
+
+mod pubkey_map_string {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        map: &BTreeMap<Pubkey, ReserveAddresses>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(map.iter().map(|(key, value)| (key.to_string(), value)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<Pubkey, ReserveAddresses>, D::Error> {
+        deserialize_pubkey_map(deserializer)
+    }
+}
+
+mod optional_keypair_bytes {
+    use super::*;
+    use serde::de;
+    use zeroize::Zeroizing;
+
+    pub fn serialize<S: Serializer>(
+        keypair: &Option<SecretKeypair>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        keypair
+            .as_ref()
+            .map(|keypair| Zeroizing::new(keypair.as_bytes().to_vec()))
+            .as_deref()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SecretKeypair>, D::Error> {
+        Option::<Vec<u8>>::deserialize(deserializer)?
+            .map(Zeroizing::new)
+            .map(|bytes| SecretKeypair::from_bytes(&bytes).map_err(de::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config::keypair::keypair_bytes, instruction::LendingInstruction};
+    use std::str::FromStr;
+
+    #[test]
+    fn load_address_books() {
+        for (json, reserves) in [
+            (
+                include_str!("../../config/lending-market-mainnet-product.json"),
+                21,
+            ),
+            (
+                include_str!("../../config/lending-market-mainnet-test.json"),
+                25,
+            ),
+        ] {
+            let address_book = MarketAddressBook::from_json(json).unwrap();
+            assert_eq!(address_book.reserves.len(), reserves);
+        }
+    }
+
+    #[test]
+    fn deposit_by_symbol() {
+        let config =
+            MarketConfig::from_json(include_str!("../../config/config-product.json")).unwrap();
+        let address_book = MarketAddressBook::from_json(include_str!(
+            "../../config/lending-market-mainnet-product.json"
+        ))
+        .unwrap();
+        assert!(address_book.reserve_by_symbol("USDC").is_err());
+        let address_book = address_book.with_names(&config);
+
+        let usdc = address_book.reserve_accounts("USDC").unwrap();
+        assert_eq!(
+            usdc.reserve,
+            Pubkey::from_str("Emq1qT9MyyB5eHfftF5thYme84hoEwh4TCjm31K2Xxif").unwrap()
+        );
+        let instruction = address_book
+            .deposit_reserve_liquidity(
+                Pubkey::default(),
+                "USDC",
+                100,
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+            )
+            .unwrap();
+        assert_eq!(instruction.accounts[2].pubkey, usdc.reserve);
+        assert_eq!(instruction.accounts[4].pubkey, usdc.liquidity_supply);
+        assert_eq!(
+            LendingInstruction::unpack(&instruction.data),
+            Ok(LendingInstruction::DepositReserveLiquidity {
+                liquidity_amount: 100
+            })
+        );
+
+        // the reserve accounts of this LP reserve are not in the address book
+        assert!(matches!(
+            address_book.reserve_accounts("mSOL-USDC"),
+            Err(ConfigError::InvalidReserve { .. })
+        ));
+        assert!(matches!(
+            address_book.reserve_accounts("DOGE"),
+            Err(ConfigError::UnknownReserve(_))
+        ));
+        let farm = &address_book.reserve_by_symbol("SOL-USDT").unwrap().lp;
+        assert!(farm.farm_pool.is_some() && farm.farm_reward_vault_b.is_some());
+    }
+
+    #[test]
+    fn embedded_secret_is_opt_in() {
+        let json = include_str!("../../config/lending-market-mainnet-test.json");
+        let address_book = MarketAddressBook::from_json(json).unwrap();
+        assert!(!address_book.has_embedded_secret());
+        assert_eq!(
+            MarketAddressBook::from_json(&address_book.to_json(EmbeddedSecret::Refuse).unwrap())
+                .unwrap(),
+            address_book
+        );
+
+        let bytes = keypair_bytes([7; 32]);
+        let lending_market = SecretKeypair::from_bytes(&bytes).unwrap().pubkey();
+        let json = json.replace(
+            &address_book.lending_market.to_string(),
+            &lending_market.to_string(),
+        );
+        let embedded = json.replacen("{", &format!("{{\n  \"account\": {:?},", bytes), 1);
+        assert!(matches!(
+            MarketAddressBook::from_json(&embedded),
+            Err(ConfigError::EmbeddedSecret)
+        ));
+        let mut address_book =
+            MarketAddressBook::from_json_with(&embedded, EmbeddedSecret::Allow).unwrap();
+        assert!(address_book.has_embedded_secret());
+        assert!(matches!(
+            address_book.to_json(EmbeddedSecret::Refuse),
+            Err(ConfigError::EmbeddedSecret)
+        ));
+        let written = address_book.to_json(EmbeddedSecret::Allow).unwrap();
+        assert!(MarketAddressBook::from_json_with(&written, EmbeddedSecret::Allow).is_ok());
+
+        address_book.remove_embedded_secret();
+        assert!(MarketAddressBook::from_json(
+            &address_book.to_json(EmbeddedSecret::Refuse).unwrap()
+        )
+        .is_ok());
+
+        // the embedded keypair must be the lending market keypair
+        let other = keypair_bytes([8; 32]);
+        let mismatched = json.replacen("{", &format!("{{\n  \"account\": {:?},", other), 1);
+        assert!(matches!(
+            MarketAddressBook::from_json_with(&mismatched, EmbeddedSecret::Allow),
+            Err(ConfigError::InvalidKeypair { .. })
+        ));
+    }
+}
@@ -0,0 +1,621 @@
+//! Drift between a market config and the on-chain state of its reserves.
+//!
+//! Reserve accounts are read from the bytes of `solana account --output json` dumps and
+//! compared with the reserve entry of their liquidity mint. Reserves owned by another program
+//! or belonging to another lending market are reported as such and not compared. Rate, fee
+//! and pause flag drift can be reconciled with `SetConfig`, oracle and mining drift is only
+//! reported.
+
+use super::{deserialize_pubkey, ConfigError, MarketConfig, ReserveEntry};
+use crate::{instruction::set_config, state::reserve::Reserve};
+use serde::Deserialize;
+use solana_program::{instruction::Instruction, program_pack::Pack, pubkey::Pubkey};
+use std::fmt;
+
+/// Kind of a drifted field
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DriftKind {
+    /// Rates, fees and pause flags, reconciled by `SetConfig`
+    Config,
+    /// Oracle accounts
+    Oracle,
+    /// Mining speed and split
+    Mining,
+}
+
+/// Field whose on-chain value differs from the config
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldDrift {
+    /// Kind of the field
+    pub kind: DriftKind,
+    /// Field name, as in the config file
+    pub field: &'static str,
+    /// Value in the config
+    pub expected: String,
+    /// Value on chain
+    pub actual: String,
+}
+
+/// Drift of a reserve
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReserveDrift {
+    /// Reserve account
+    pub reserve: Pubkey,
+    /// Lending market of the reserve
+    pub lending_market: Pubkey,
+    /// Name of the reserve entry
+    pub name: String,
+    /// Drifted fields
+    pub fields: Vec<FieldDrift>,
+    /// Reserve state with the config values of the entry
+    expected: Reserve,
+}
+
+impl ReserveDrift {
+    /// Compare a reserve with its entry in the config
+    pub fn new(reserve_pubkey: Pubkey, reserve: &Reserve, entry: &ReserveEntry) -> Self {
+        let mut expected = reserve.clone();
+        let expected_config = entry.reserve_config();
+        let config = &mut expected.config;
+        config.optimal_utilization_rate = expected_config.optimal_utilization_rate;
+        config.loan_to_value_ratio = expected_config.loan_to_value_ratio;
+        config.liquidation_bonus = expected_config.liquidation_bonus;
+        config.liquidation_threshold = expected_config.liquidation_threshold;
+        config.min_borrow_rate = expected_config.min_borrow_rate;
+        config.optimal_borrow_rate = expected_config.optimal_borrow_rate;
+        config.max_borrow_rate = expected_config.max_borrow_rate;
+        config.fees.borrow_fee_wad = expected_config.fees.borrow_fee_wad;
+        config.fees.reserve_owner_fee_wad = expected_config.fees.reserve_owner_fee_wad;
+        config.fees.flash_loan_fee_wad = expected_config.fees.flash_loan_fee_wad;
+        config.fees.host_fee_percentage = expected_config.fees.host_fee_percentage;
+        config.deposit_paused = expected_config.deposit_paused;
+        config.borrow_paused = expected_config.borrow_paused;
+        config.liquidation_paused = expected_config.liquidation_paused;
+
+        let liquidity = &mut expected.liquidity;
+        liquidity.is_lp = entry.is_lp;
+        // an unset oracle choice is not compared
+        liquidity.use_pyth_oracle = entry.use_pyth_oracle().unwrap_or(liquidity.use_pyth_oracle);
+        let (params_1, params_2) = if entry.is_lp {
+            let bridge_pool = entry.bridge_pool.clone().unwrap_or_default();
+            (bridge_pool.pool, bridge_pool.lp_price)
+        } else {
+            (entry.price, entry.larix_oracle_id)
+        };
+        // unset accounts are not compared
+        liquidity.params_1 = params_1.unwrap_or(liquidity.params_1);
+        liquidity.params_2 = params_2.unwrap_or(liquidity.params_2);
+
+        expected.bonus.total_mining_speed = entry.total_mining_speed;
+        expected.bonus.supply_rate = entry.kink_util_rate;
+
+        let mut drift = Self {
+            reserve: reserve_pubkey,
+            lending_market: reserve.lending_market,
+            name: entry.name.clone(),
+            fields: vec![],
+            expected,
+        };
+        drift.compare_fields(reserve);
+        drift
+    }
+
+    fn compare_fields(&mut self, actual: &Reserve) {
+        let expected = &self.expected;
+        let mut fields = vec![];
+        let mut compare = |kind, field, expected: &dyn fmt::Display, actual: &dyn fmt::Display| {
+            let (expected, actual) = (expected.to_string(), actual.to_string());
+            if expected != actual {
+                fields.push(FieldDrift {
+                    kind,
+                    field,
+                    expected,
+                    actual,
+                });
+            }
+        };
+        let (config, actual_config) = (&expected.config, &actual.config);
+        compare(
+            DriftKind::Config,
+            "optimal_utilization_rate",
+            &config.optimal_utilization_rate,
+            &actual_config.optimal_utilization_rate,
+        );
+        compare(
+            DriftKind::Config,
+            "loan_to_value_ratio",
+            &config.loan_to_value_ratio,
+            &actual_config.loan_to_value_ratio,
+        );
+        compare(
+            DriftKind::Config,
+            "liquidation_bonus",
+            &config.liquidation_bonus,
+            &actual_config.liquidation_bonus,
+        );
+        compare(
+            DriftKind::Config,
+            "liquidation_threshold",
+            &config.liquidation_threshold,
+            &actual_config.liquidation_threshold,
+        );
+        compare(
+            DriftKind::Config,
+            "min_borrow_rate",
+            &config.min_borrow_rate,
+            &actual_config.min_borrow_rate,
+        );
+        compare(
+            DriftKind::Config,
+            "optimal_borrow_rate",
+            &config.optimal_borrow_rate,
+            &actual_config.optimal_borrow_rate,
+        );
+        compare(
+            DriftKind::Config,
+            "max_borrow_rate",
+            &config.max_borrow_rate,
+            &actual_config.max_borrow_rate,
+        );
+        compare(
+            DriftKind::Config,
+            "borrow_fee_wad",
+            &config.fees.borrow_fee_wad,
+            &actual_config.fees.borrow_fee_wad,
+        );
+        compare(
+            DriftKind::Config,
+            "borrow_interest_fee_wad",
+            &config.fees.reserve_owner_fee_wad,
+            &actual_config.fees.reserve_owner_fee_wad,
+        );
+        compare(
+            DriftKind::Config,
+            "flash_loan_fee_wad",
+            &config.fees.flash_loan_fee_wad,
+            &actual_config.fees.flash_loan_fee_wad,
+        );
+        compare(
+            DriftKind::Config,
+            "host_fee_percentage",
+            &config.fees.host_fee_percentage,
+            &actual_config.fees.host_fee_percentage,
+        );
+        compare(
+            DriftKind::Config,
+            "deposit_paused",
+            &config.deposit_paused,
+            &actual_config.deposit_paused,
+        );
+        compare(
+            DriftKind::Config,
+            "borrow_paused",
+            &config.borrow_paused,
+            &actual_config.borrow_paused,
+        );
+        compare(
+            DriftKind::Config,
+            "liquidation_paused",
+            &config.liquidation_paused,
+            &actual_config.liquidation_paused,
+        );
+
+        let (liquidity, actual_liquidity) = (&expected.liquidity, &actual.liquidity);
+        compare(
+            DriftKind::Oracle,
+            "is_lp",
+            &liquidity.is_lp,
+            &actual_liquidity.is_lp,
+        );
+        compare(
+            DriftKind::Oracle,
+            "use_pyth_oracle",
+            &liquidity.use_pyth_oracle,
+            &actual_liquidity.use_pyth_oracle,
+        );
+        compare(
+            DriftKind::Oracle,
+            if liquidity.is_lp {
+                "bridge_pool.pool"
+            } else {
+                "price"
+            },
+            &liquidity.params_1,
+            &actual_liquidity.params_1,
+        );
+        compare(
+            DriftKind::Oracle,
+            if liquidity.is_lp {
+                "bridge_pool.lp_price"
+            } else {
+                "larix_oracle_id"
+            },
+            &liquidity.params_2,
+            &actual_liquidity.params_2,
+        );
+
+        compare(
+            DriftKind::Mining,
+            "total_mining_speed",
+            &expected.bonus.total_mining_speed,
+            &actual.bonus.total_mining_speed,
+        );
+        compare(
+            DriftKind::Mining,
+            "kink_util_rate",
+            &expected.bonus.supply_rate,
+            &actual.bonus.supply_rate,
+        );
+        self.fields = fields;
+    }
+
+    /// True if no field drifted
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// `SetConfig` instruction reconciling the rates, fees and pause flags of the reserve,
+    /// `None` if they did not drift
+    pub fn set_config(
+        &self,
+        program_id: Pubkey,
+        lending_market_owner: Pubkey,
+    ) -> Option<Instruction> {
+        if !self
+            .fields
+            .iter()
+            .any(|field| field.kind == DriftKind::Config)
+        {
+            return None;
+        }
+        Some(set_config(
+            program_id,
+            self.expected.config.clone(),
+            self.reserve,
+            self.lending_market,
+            lending_market_owner,
+        ))
+    }
+}
+
+/// Drift report of a set of reserves
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DriftReport {
+    /// Drift of the reserves found in the config, in the order they were given
+    pub reserves: Vec<ReserveDrift>,
+    /// Reserves whose liquidity mint is not in the config, with the mint
+    pub unknown_reserves: Vec<(Pubkey, Pubkey)>,
+    /// Accounts that are not reserves of the lending market, with the reason
+    pub mismatched_reserves: Vec<(Pubkey, String)>,
+}
+
+impl DriftReport {
+    /// Compare reserve accounts of a lending market with the config
+    pub fn new(
+        config: &MarketConfig,
+        lending_market: &Pubkey,
+        reserves: &[(Pubkey, Reserve)],
+    ) -> Self {
+        let mut report = Self::default();
+        for (reserve_pubkey, reserve) in reserves {
+            if reserve.lending_market != *lending_market {
+                report.mismatched_reserves.push((
+                    *reserve_pubkey,
+                    format!(
+                        "lending market {} is not {}",
+                        reserve.lending_market, lending_market
+                    ),
+                ));
+                continue;
+            }
+            match config.reserves.get(&reserve.liquidity.mint_pubkey) {
+                Some(entry) => {
+                    report
+                        .reserves
+                        .push(ReserveDrift::new(*reserve_pubkey, reserve, entry))
+                }
+                None => report
+                    .unknown_reserves
+                    .push((*reserve_pubkey, reserve.liquidity.mint_pubkey)),
+            }
+        }
+        report
+    }
+
+    /// Compare reserve accounts of a lending market, as `solana account --output json` dumps,
+    /// with the config
+    pub fn from_account_dumps<S: AsRef<str>>(
+        config: &MarketConfig,
+        program_id: &Pubkey,
+        lending_market: &Pubkey,
+        dumps: &[S],
+    ) -> Result<Self, ConfigError> {
+        let mut reserves = vec![];
+        let mut mismatched_reserves = vec![];
+        for dump in dumps {
+            let dump = unpack_reserve_dump(dump.as_ref())?;
+            if dump.owner == *program_id {
+                reserves.push((dump.pubkey, dump.reserve));
+            } else {
+                mismatched_reserves.push((
+                    dump.pubkey,
+                    format!("owner {} is not {}", dump.owner, program_id),
+                ));
+            }
+        }
+        let mut report = Self::new(config, lending_market, &reserves);
+        report.mismatched_reserves.extend(mismatched_reserves);
+        Ok(report)
+    }
+
+    /// True if no reserve drifted and all reserves are reserves of the lending market in the
+    /// config
+    pub fn is_empty(&self) -> bool {
+        self.unknown_reserves.is_empty()
+            && self.mismatched_reserves.is_empty()
+            && self.reserves.iter().all(ReserveDrift::is_empty)
+    }
+
+    /// `SetConfig` instructions reconciling the rates, fees and pause flags of the reserves
+    pub fn reconcile(&self, program_id: Pubkey, lending_market_owner: Pubkey) -> Vec<Instruction> {
+        self.reserves
+            .iter()
+            .filter_map(|drift| drift.set_config(program_id, lending_market_owner))
+            .collect()
+    }
+}
+
+impl fmt::Display for DriftReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for drift in self.reserves.iter().filter(|drift| !drift.is_empty()) {
+            writeln!(f, "{} ({}):", drift.name, drift.reserve)?;
+            for field in &drift.fields {
+                writeln!(
+                    f,
+                    "  {}: config {}, on chain {}",
+                    field.field, field.expected, field.actual
+                )?;
+            }
+        }
+        for (reserve, mint) in &self.unknown_reserves {
+            writeln!(f, "{}: liquidity mint {} not in config", reserve, mint)?;
+        }
+        for (reserve, reason) in &self.mismatched_reserves {
+            writeln!(f, "{}: not compared, {}", reserve, reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct AccountDump {
+    #[serde(deserialize_with = "deserialize_pubkey")]
+    pubkey: Pubkey,
+    account: AccountDumpData,
+}
+
+#[derive(Deserialize)]
+struct AccountDumpData {
+    /// Data and its encoding
+    data: (String, String),
+    #[serde(deserialize_with = "deserialize_pubkey")]
+    owner: Pubkey,
+}
+
+/// Reserve account read from a dump
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReserveDump {
+    /// Reserve account
+    pub pubkey: Pubkey,
+    /// Program owning the account
+    pub owner: Pubkey,
+    /// Reserve state
+    pub reserve: Reserve,
+}
+
+/// Reserve of a `solana account --output json` dump
+pub fn unpack_reserve_dump(dump: &str) -> Result<ReserveDump, ConfigError> {
+    let dump: AccountDump = serde_json::from_str(dump)?;
+    let invalid = |reason: String| ConfigError::InvalidAccount {
+        pubkey: dump.pubkey,
+        reason,
+    };
+    let (data, encoding) = &dump.account.data;
+    if encoding != "base64" {
+        return Err(invalid(format!("unsupported encoding {}", encoding)));
+    }
+    let data = base64::decode(data).map_err(|error| invalid(error.to_string()))?;
+    let reserve = Reserve::unpack(&data).map_err(|error| invalid(error.to_string()))?;
+    Ok(ReserveDump {
+        pubkey: dump.pubkey,
+        owner: dump.account.owner,
+        reserve,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        instruction::LendingInstruction,
+        state::{reserve::ReserveLiquidity, PROGRAM_VERSION},
+    };
+    use std::str::FromStr;
+
+    fn reserve_of(entry: &ReserveEntry, liquidity_mint: Pubkey, lending_market: Pubkey) -> Reserve {
+        let mut reserve = Reserve {
+            version: PROGRAM_VERSION,
+            lending_market,
+            config: entry.reserve_config(),
+            ..Reserve::default()
+        };
+        reserve.liquidity.mint_pubkey = liquidity_mint;
+        reserve.liquidity.use_pyth_oracle = true;
+        reserve.liquidity.params_1 = entry.price.unwrap();
+        reserve.liquidity.params_2 = entry.larix_oracle_id.unwrap();
+        reserve.bonus.total_mining_speed = entry.total_mining_speed;
+        reserve.bonus.supply_rate = entry.kink_util_rate;
+        reserve
+    }
+
+    fn dump(pubkey: Pubkey, owner: Pubkey, reserve: &Reserve) -> String {
+        let mut data = [0; Reserve::LEN];
+        Reserve::pack(reserve.clone(), &mut data).unwrap();
+        format!(
+            r#"{{"pubkey":"{}","account":{{"lamports":1,"data":["{}","base64"],"owner":"{}","executable":false,"rentEpoch":0}}}}"#,
+            pubkey,
+            base64::encode(&data[..]),
+            owner
+        )
+    }
+
+    #[test]
+    fn drift_is_reported() {
+        let config =
+            MarketConfig::from_json(include_str!("../../config/config-test.json")).unwrap();
+        let usdt = Pubkey::from_str("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB").unwrap();
+        let entry = &config.reserves[&usdt];
+        let program_id = Pubkey::new_unique();
+        let lending_market = Pubkey::new_unique();
+        let reserve_pubkey = Pubkey::new_unique();
+        let mut reserve = reserve_of(entry, usdt, lending_market);
+
+        let report = DriftReport::from_account_dumps(
+            &config,
+            &program_id,
+            &lending_market,
+            &[dump(reserve_pubkey, program_id, &reserve)],
+        )
+        .unwrap();
+        assert!(report.is_empty());
+
+        reserve.config.loan_to_value_ratio += 1;
+        reserve.liquidity.params_2 = Pubkey::new_unique();
+        reserve.bonus.total_mining_speed += 1;
+        let unknown = Reserve {
+            liquidity: ReserveLiquidity {
+                mint_pubkey: Pubkey::new_unique(),
+                ..reserve.liquidity.clone()
+            },
+            ..reserve.clone()
+        };
+        let report = DriftReport::from_account_dumps(
+            &config,
+            &program_id,
+            &lending_market,
+            &[
+                dump(reserve_pubkey, program_id, &reserve),
+                dump(Pubkey::new_unique(), program_id, &unknown),
+            ],
+        )
+        .unwrap();
+        let fields: Vec<_> = report.reserves[0]
+            .fields
+            .iter()
+            .map(|field| (field.kind, field.field))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                (DriftKind::Config, "loan_to_value_ratio"),
+                (DriftKind::Oracle, "larix_oracle_id"),
+                (DriftKind::Mining, "total_mining_speed"),
+            ]
+        );
+        assert_eq!(report.unknown_reserves.len(), 1);
+
+        // the oracle choice is only compared when the entry sets it
+        let mut explicit = config.clone();
+        explicit.reserves.get_mut(&usdt).unwrap().use_pyth_oracle = Some(false);
+        let report = DriftReport::new(&explicit, &lending_market, &[(reserve_pubkey, reserve)]);
+        assert!(report.reserves[0]
+            .fields
+            .iter()
+            .any(|field| field.field == "use_pyth_oracle"));
+    }
+
+    #[test]
+    fn reconcile_removes_config_drift() {
+        let config =
+            MarketConfig::from_json(include_str!("../../config/config-test.json")).unwrap();
+        let usdt = Pubkey::from_str("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB").unwrap();
+        let program_id = Pubkey::new_unique();
+        let lending_market = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let reserve_pubkey = Pubkey::new_unique();
+        let mut reserve = reserve_of(&config.reserves[&usdt], usdt, lending_market);
+        assert!(DriftReport::new(
+            &config,
+            &lending_market,
+            &[(reserve_pubkey, reserve.clone())]
+        )
+        .reconcile(program_id, owner)
+        .is_empty());
+
+        reserve.config.loan_to_value_ratio += 1;
+        reserve.config.fees.flash_loan_fee_wad += 1;
+        reserve.config.borrow_paused = !reserve.config.borrow_paused;
+        reserve.config.deposit_limit = 1_000;
+        reserve.liquidity.params_2 = Pubkey::new_unique();
+        let report = DriftReport::new(
+            &config,
+            &lending_market,
+            &[(reserve_pubkey, reserve.clone())],
+        );
+        let instructions = report.reconcile(program_id, owner);
+        assert_eq!(instructions.len(), 1);
+        let instruction = &instructions[0];
+        assert_eq!(instruction.program_id, program_id);
+        assert_eq!(instruction.accounts[0].pubkey, reserve_pubkey);
+        assert_eq!(instruction.accounts[1].pubkey, lending_market);
+        assert_eq!(instruction.accounts[2].pubkey, owner);
+        assert!(instruction.accounts[2].is_signer);
+
+        match LendingInstruction::unpack(&instruction.data) {
+            Ok(LendingInstruction::SetConfig { config }) => reserve.config = config,
+            _ => panic!("expected SetConfig"),
+        }
+        // the deposit limit is not in the config file and is kept
+        assert_eq!(reserve.config.deposit_limit, 1_000);
+        let report = DriftReport::new(&config, &lending_market, &[(reserve_pubkey, reserve)]);
+        let fields: Vec<_> = report.reserves[0]
+            .fields
+            .iter()
+            .map(|field| (field.kind, field.field))
+            .collect();
+        assert_eq!(fields, vec![(DriftKind::Oracle, "larix_oracle_id")]);
+        assert!(report.reconcile(program_id, owner).is_empty());
+    }
+
+    #[test]
+    fn other_markets_are_not_compared() {
+        let config =
+            MarketConfig::from_json(include_str!("../../config/config-test.json")).unwrap();
+        let usdt = Pubkey::from_str("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB").unwrap();
+        let program_id = Pubkey::new_unique();
+        let lending_market = Pubkey::new_unique();
+        let mut reserve = reserve_of(&config.reserves[&usdt], usdt, Pubkey::new_unique());
+        reserve.config.loan_to_value_ratio += 1;
+        let other_market = Pubkey::new_unique();
+        let other_program = Pubkey::new_unique();
+
+        let report = DriftReport::from_account_dumps(
+            &config,
+            &program_id,
+            &lending_market,
+            &[
+                dump(other_market, program_id, &reserve),
+                dump(other_program, Pubkey::new_unique(), &reserve),
+            ],
+        )
+        .unwrap();
+        assert!(report.reserves.is_empty());
+        let mismatched: Vec<_> = report
+            .mismatched_reserves
+            .iter()
+            .map(|(reserve, _)| *reserve)
+            .collect();
+        assert_eq!(mismatched, vec![other_market, other_program]);
+        assert!(!report.is_empty());
+    }
+}
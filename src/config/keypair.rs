@@ -0,0 +1,145 @@
+//! Keypairs read from their own files, kept out of the market config files.
+//!
+//! Keypair files use the format of `solana-keygen`: a JSON array of the 64 bytes of the
+//! secret key followed by the public key.
+//!
+//! Removing a keypair from a committed file does not make it secret again. The lending market
+//! keypairs the `config/lending-market-mainnet-*.json` address books used to embed are still in
+//! the repository history, so they must be treated as compromised and rotated: move the
+//! ownership of those markets to new keys, kept in keypair files outside the repository.
+
+use super::ConfigError;
+use ed25519_dalek::{PublicKey, SecretKey};
+use solana_program::pubkey::Pubkey;
+use std::{env, fmt, fs, path::Path};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Environment variable holding the path of the lending market keypair file
+pub const LENDING_MARKET_KEYPAIR_ENV: &str = "LENDING_MARKET_KEYPAIR";
+
+/// Length of a keypair in bytes
+pub const KEYPAIR_LEN: usize = 64;
+
+/// Whether a config file may embed secret keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmbeddedSecret {
+    /// Fail to load or write a config file embedding a secret key
+    Refuse,
+    /// Load and write embedded secret keys
+    Allow,
+}
+
+/// Ed25519 keypair bytes, zeroized on drop and left out of `Debug` output
+///
+/// The public half is checked against the public key derived from the secret half when the
+/// keypair is read.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecretKeypair([u8; KEYPAIR_LEN]);
+
+impl SecretKeypair {
+    /// Keypair of 64 bytes whose public half is the public key of its secret half
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ConfigError> {
+        if bytes.len() != KEYPAIR_LEN {
+            return Err(ConfigError::InvalidKeypair {
+                reason: format!("expected {} bytes, got {}", KEYPAIR_LEN, bytes.len()),
+            });
+        }
+        let mut keypair = Self([0; KEYPAIR_LEN]);
+        keypair.0.copy_from_slice(bytes);
+        let secret = SecretKey::from_bytes(&keypair.0[..32]).map_err(|error| {
+            ConfigError::InvalidKeypair {
+                reason: error.to_string(),
+            }
+        })?;
+        if PublicKey::from(&secret).as_bytes()[..] != keypair.0[32..] {
+            return Err(ConfigError::InvalidKeypair {
+                reason: "public key does not match the secret key".to_string(),
+            });
+        }
+        Ok(keypair)
+    }
+
+    /// Read a keypair file
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let json = Zeroizing::new(fs::read_to_string(path)?);
+        let bytes: Zeroizing<Vec<u8>> = Zeroizing::new(serde_json::from_str(&json)?);
+        Self::from_bytes(&bytes)
+    }
+
+    /// Read the keypair file at the path held by an environment variable
+    pub fn read_from_env(var: &str) -> Result<Self, ConfigError> {
+        let path = env::var_os(var).ok_or_else(|| ConfigError::InvalidKeypair {
+            reason: format!("environment variable {} is not set", var),
+        })?;
+        Self::read(path)
+    }
+
+    /// Public key, derived from the secret key when the keypair was read
+    pub fn pubkey(&self) -> Pubkey {
+        let mut pubkey = [0; 32];
+        pubkey.copy_from_slice(&self.0[32..]);
+        Pubkey::new_from_array(pubkey)
+    }
+
+    /// Keypair bytes, e.g. for `solana_sdk::signature::Keypair::from_bytes`
+    pub fn as_bytes(&self) -> &[u8; KEYPAIR_LEN] {
+        &self.0
+    }
+
+    /// Check the keypair is the keypair of an account
+    pub fn check_pubkey(&self, expected: &Pubkey) -> Result<(), ConfigError> {
+        if self.pubkey() != *expected {
+            return Err(ConfigError::InvalidKeypair {
+                reason: format!("keypair of {} given for {}", self.pubkey(), expected),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for SecretKeypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretKeypair({}, <secret key>)", self.pubkey())
+    }
+}
+
+impl Drop for SecretKeypair {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Keypair bytes of a secret key
+#[cfg(test)]
+pub(crate) fn keypair_bytes(secret: [u8; 32]) -> Vec<u8> {
+    let public = PublicKey::from(&SecretKey::from_bytes(&secret).unwrap());
+    let mut bytes = secret.to_vec();
+    bytes.extend_from_slice(public.as_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keypair_is_checked_and_kept_out_of_debug() {
+        let bytes = keypair_bytes([7; 32]);
+        let keypair = SecretKeypair::from_bytes(&bytes).unwrap();
+        let pubkey = keypair.pubkey();
+        assert_eq!(pubkey.as_ref(), &bytes[32..]);
+        assert_eq!(&keypair.as_bytes()[..], &bytes[..]);
+        assert!(keypair.check_pubkey(&pubkey).is_ok());
+        assert!(keypair.check_pubkey(&Pubkey::new_unique()).is_err());
+        assert_eq!(
+            format!("{:?}", keypair),
+            format!("SecretKeypair({}, <secret key>)", pubkey)
+        );
+        assert!(SecretKeypair::from_bytes(&bytes[..32]).is_err());
+
+        // the public half must be the public key of the secret half
+        let mut mismatched = bytes;
+        mismatched[32..].copy_from_slice(Pubkey::new_unique().as_ref());
+        assert!(SecretKeypair::from_bytes(&mismatched).is_err());
+    }
+}
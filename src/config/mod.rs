@@ -0,0 +1,346 @@
+//! Typed model of the market config files in `config/`, such as `config/config-test.json`.
+//!
+//! A market config lists the oracle and mine accounts of a lending market and its reserves,
+//! keyed by liquidity mint. Only available with the `config` feature.
+
+#![cfg(feature = "config")]
+
+pub mod address_book;
+pub mod drift;
+pub mod keypair;
+pub mod plan;
+
+use crate::{
+    math::WAD,
+    state::reserve::{InitBonusParams, ReserveConfig, ReserveFees},
+};
+use serde::{de, Deserialize, Deserializer};
+use solana_program::pubkey::Pubkey;
+use std::{collections::BTreeMap, fs, path::Path, str::FromStr};
+use thiserror::Error;
+
+/// Errors loading a market config
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// The config file could not be read
+    #[error("Failed to read config file: {0}")]
+    Io(#[from] std::io::Error),
+    /// The config file is not valid JSON or does not match the model
+    #[error("Failed to parse config file: {0}")]
+    Json(#[from] serde_json::Error),
+    /// A reserve entry has inconsistent values
+    #[error("Invalid config of reserve {name}: {reason}")]
+    InvalidReserve {
+        /// Name of the reserve entry
+        name: String,
+        /// What is wrong with it
+        reason: String,
+    },
+    /// No reserve has the name
+    #[error("Unknown reserve {0}")]
+    UnknownReserve(String),
+    /// The config file embeds a secret key and was not loaded or written with
+    /// `EmbeddedSecret::Allow`
+    #[error("Config embeds a secret key, keep keypairs in their own files")]
+    EmbeddedSecret,
+    /// A keypair file is invalid or belongs to another account
+    #[error("Invalid keypair: {reason}")]
+    InvalidKeypair {
+        /// What is wrong with it
+        reason: String,
+    },
+    /// An account dump cannot be read
+    #[error("Invalid account {pubkey}: {reason}")]
+    InvalidAccount {
+        /// Address of the account
+        pubkey: Pubkey,
+        /// What is wrong with it
+        reason: String,
+    },
+}
+
+/// Lending market config
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct MarketConfig {
+    /// Address book file written after deployment, see `config/lending-market-*.json`
+    pub file: String,
+    /// Oracle (Pyth) program id
+    #[serde(deserialize_with = "deserialize_pubkey")]
+    pub oracle_program: Pubkey,
+    /// Mint address of the mine token
+    #[serde(deserialize_with = "deserialize_pubkey")]
+    pub mine_mint: Pubkey,
+    /// Oracle (Larix) program id
+    #[serde(deserialize_with = "deserialize_pubkey")]
+    pub larix_oracle_program_id: Pubkey,
+    /// Oracle id
+    #[serde(deserialize_with = "deserialize_pubkey")]
+    pub larix_oracle_id: Pubkey,
+    /// Reserves by liquidity mint
+    #[serde(deserialize_with = "deserialize_pubkey_map")]
+    pub reserves: BTreeMap<Pubkey, ReserveEntry>,
+}
+
+impl MarketConfig {
+    /// Parse and validate a market config
+    pub fn from_json(json: &str) -> Result<Self, ConfigError> {
+        let config: Self = serde_json::from_str(json)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Read, parse and validate a market config file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Check every reserve entry
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.reserves.values().try_for_each(ReserveEntry::validate)
+    }
+
+    /// Reserve entries to create, by liquidity mint
+    pub fn reserves_to_create(&self) -> impl Iterator<Item = (&Pubkey, &ReserveEntry)> {
+        self.reserves.iter().filter(|(_, reserve)| reserve.if_create)
+    }
+}
+
+/// Reserve entry of a market config
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ReserveEntry {
+    /// Display name, e.g. "USDC" or "SOL-USDC" for LP tokens
+    pub name: String,
+    /// Pyth product account, unset for LP reserves
+    #[serde(default, deserialize_with = "deserialize_optional_pubkey")]
+    pub product_data: Option<Pubkey>,
+    /// Pyth price account, unset for LP reserves
+    #[serde(default, deserialize_with = "deserialize_optional_pubkey")]
+    pub price: Option<Pubkey>,
+    /// Larix oracle price account, unset for LP reserves
+    #[serde(default, deserialize_with = "deserialize_optional_pubkey")]
+    pub larix_oracle_id: Option<Pubkey>,
+    /// Price the reserve with the Pyth price account rather than the Larix oracle, required
+    /// to plan the creation of a non-LP reserve
+    #[serde(default)]
+    pub use_pyth_oracle: Option<bool>,
+    /// Optimal utilization rate, as a percentage
+    pub optimal_utilization_rate: u8,
+    /// Target ratio of the value of borrows to deposits, as a percentage
+    pub loan_to_value_ratio: u8,
+    /// Bonus a liquidator gets when repaying part of an unhealthy obligation, as a percentage
+    pub liquidation_bonus: u8,
+    /// Loan to value ratio at which an obligation can be liquidated, as a percentage
+    pub liquidation_threshold: u8,
+    /// Min borrow APY
+    pub min_borrow_rate: u8,
+    /// Optimal (utilization) borrow APY
+    pub optimal_borrow_rate: u8,
+    /// Max borrow APY
+    pub max_borrow_rate: u8,
+    /// Fee assessed on borrows, expressed as a Wad
+    pub borrow_fee_wad: u64,
+    /// Share of borrow interest going to the reserve owner, expressed as a Wad
+    pub borrow_interest_fee_wad: u64,
+    /// Fee for flash loans, expressed as a Wad
+    pub flash_loan_fee_wad: u64,
+    /// Amount of fee going to host account, as a percentage
+    pub host_fee_percentage: u8,
+    /// If deposit paused
+    pub deposit_paused: bool,
+    /// If borrow paused
+    pub borrow_paused: bool,
+    /// If liquidation paused
+    pub liquidation_paused: bool,
+    /// Mine emitted by the reserve per slot, written as a decimal string
+    #[serde(deserialize_with = "deserialize_u64_string")]
+    pub total_mining_speed: u64,
+    /// Split of mining between supply and borrow, stored as `Bonus::supply_rate`
+    pub kink_util_rate: u64,
+    /// Is the liquidity mint an LP token
+    #[serde(default)]
+    pub is_lp: bool,
+    /// Bridge accounts of an LP reserve
+    #[serde(default)]
+    pub bridge_pool: Option<BridgePoolEntry>,
+    /// Create the reserve when deploying the market
+    #[serde(default)]
+    pub if_create: bool,
+}
+
+/// Bridge accounts of an LP reserve
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct BridgePoolEntry {
+    /// BridgePool account of the bridge program
+    #[serde(default, deserialize_with = "deserialize_optional_pubkey")]
+    pub pool: Option<Pubkey>,
+    /// LpPrice account of the bridge program
+    #[serde(default, deserialize_with = "deserialize_optional_pubkey")]
+    pub lp_price: Option<Pubkey>,
+}
+
+impl ReserveEntry {
+    /// Check the reserve entry for values `InitReserve` would reject or misuse
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |reason: &str| {
+            Err(ConfigError::InvalidReserve {
+                name: self.name.clone(),
+                reason: reason.to_string(),
+            })
+        };
+        if self.optimal_utilization_rate > 100 {
+            return invalid("optimal utilization rate must be in range [0, 100]");
+        }
+        if self.loan_to_value_ratio >= 100 {
+            return invalid("loan to value ratio must be in range [0, 100)");
+        }
+        if self.liquidation_bonus > 100 {
+            return invalid("liquidation bonus must be in range [0, 100]");
+        }
+        if self.liquidation_threshold <= self.loan_to_value_ratio
+            || self.liquidation_threshold > 100
+        {
+            return invalid("liquidation threshold must be in range (LTV, 100]");
+        }
+        if self.optimal_borrow_rate < self.min_borrow_rate
+            || self.optimal_borrow_rate > self.max_borrow_rate
+        {
+            return invalid("optimal borrow rate must be in range [min, max]");
+        }
+        if self.borrow_fee_wad >= WAD
+            || self.borrow_interest_fee_wad >= WAD
+            || self.flash_loan_fee_wad >= WAD
+        {
+            return invalid("fees must be in range [0, 1_000_000_000_000_000_000)");
+        }
+        if self.host_fee_percentage > 100 {
+            return invalid("host fee percentage must be in range [0, 100]");
+        }
+        if !self.is_lp
+            && (self.product_data.is_none()
+                || self.price.is_none()
+                || self.larix_oracle_id.is_none())
+        {
+            return invalid("reserves need Pyth product, Pyth price and Larix oracle accounts");
+        }
+        if self.is_lp && self.use_pyth_oracle == Some(true) {
+            return invalid("LP reserves are priced by the bridge program, not Pyth");
+        }
+        Ok(())
+    }
+
+    /// Reserve config values of the entry
+    pub fn reserve_config(&self) -> ReserveConfig {
+        ReserveConfig {
+            optimal_utilization_rate: self.optimal_utilization_rate,
+            loan_to_value_ratio: self.loan_to_value_ratio,
+            liquidation_bonus: self.liquidation_bonus,
+            liquidation_threshold: self.liquidation_threshold,
+            min_borrow_rate: self.min_borrow_rate,
+            optimal_borrow_rate: self.optimal_borrow_rate,
+            max_borrow_rate: self.max_borrow_rate,
+            fees: ReserveFees {
+                borrow_fee_wad: self.borrow_fee_wad,
+                reserve_owner_fee_wad: self.borrow_interest_fee_wad,
+                flash_loan_fee_wad: self.flash_loan_fee_wad,
+                host_fee_percentage: self.host_fee_percentage,
+                host_fee_receivers: vec![],
+            },
+            deposit_paused: self.deposit_paused,
+            borrow_paused: self.borrow_paused,
+            liquidation_paused: self.liquidation_paused,
+            ..ReserveConfig::default()
+        }
+    }
+
+    /// Bonus init parameters of the entry
+    pub fn bonus_params(&self, un_coll_supply_account: Pubkey) -> InitBonusParams {
+        InitBonusParams {
+            un_coll_supply_account,
+            total_mining_speed: self.total_mining_speed,
+            supply_rate: self.kink_util_rate,
+        }
+    }
+
+    /// Use the Pyth price account as the reserve oracle, `None` if the entry does not say
+    pub fn use_pyth_oracle(&self) -> Option<bool> {
+        if self.is_lp {
+            Some(false)
+        } else {
+            self.use_pyth_oracle
+        }
+    }
+}
+
+fn parse_pubkey<E: de::Error>(s: &str) -> Result<Pubkey, E> {
+    Pubkey::from_str(s).map_err(|_| E::custom(format!("invalid pubkey {}", s)))
+}
+
+fn deserialize_pubkey<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+    parse_pubkey(&String::deserialize(deserializer)?)
+}
+
+fn deserialize_optional_pubkey<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Pubkey>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| parse_pubkey(&s))
+        .transpose()
+}
+
+fn deserialize_pubkey_map<'de, D: Deserializer<'de>, V: Deserialize<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<Pubkey, V>, D::Error> {
+    BTreeMap::<String, V>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, value)| Ok((parse_pubkey(&key)?, value)))
+        .collect()
+}
+
+fn deserialize_u64_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse()
+        .map_err(|_| de::Error::custom(format!("invalid integer {}", s)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn load_market_configs() {
+        for json in [
+            include_str!("../../config/config-test.json"),
+            include_str!("../../config/config-product.json"),
+        ] {
+            let config = MarketConfig::from_json(json).unwrap();
+            assert_eq!(config.reserves.len(), 28);
+        }
+    }
+
+    #[test]
+    fn reserve_entry_conversion() {
+        let config = MarketConfig::from_json(include_str!("../../config/config-test.json")).unwrap();
+        let usdt = Pubkey::from_str("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB").unwrap();
+        let entry = &config.reserves[&usdt];
+        assert_eq!(entry.name, "USDT");
+        assert_eq!(entry.use_pyth_oracle(), None);
+        let mut explicit = entry.clone();
+        explicit.use_pyth_oracle = Some(false);
+        assert_eq!(explicit.use_pyth_oracle(), Some(false));
+        explicit.is_lp = true;
+        assert_eq!(explicit.use_pyth_oracle(), Some(false));
+        explicit.use_pyth_oracle = Some(true);
+        assert!(explicit.validate().is_err());
+
+        let reserve_config = entry.reserve_config();
+        assert_eq!(reserve_config.loan_to_value_ratio, 75);
+        assert_eq!(reserve_config.fees.reserve_owner_fee_wad, 200_000_000_000_000_000);
+        let bonus_params = entry.bonus_params(Pubkey::default());
+        assert_eq!(bonus_params.total_mining_speed, 46);
+        assert_eq!(bonus_params.supply_rate, 50);
+
+        let mut invalid = entry.clone();
+        invalid.liquidation_threshold = invalid.loan_to_value_ratio;
+        assert!(invalid.validate().is_err());
+    }
+}
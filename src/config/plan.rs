@@ -0,0 +1,367 @@
+//! Deployment plan of a market config.
+//!
+//! The plan is the ordered list of instructions standing up a lending market and the reserves
+//! marked `if_create`. Keys of new accounts are passed in, so the same config and keys always
+//! produce the same plan, which can be reviewed before it is executed.
+
+use super::{ConfigError, MarketConfig, ReserveEntry};
+use crate::{
+    instruction::{init_lending_market, init_reserve, set_config, InitReserveAccounts},
+    state::{lending_market::LendingMarket, reserve::Reserve},
+};
+use solana_program::{
+    instruction::Instruction, program_pack::Pack, pubkey::Pubkey, rent::Rent, system_instruction,
+};
+use std::collections::BTreeMap;
+
+/// Keys of the new accounts of a reserve
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReserveKeys {
+    /// Reserve account
+    pub reserve: Pubkey,
+    /// Reserve liquidity supply SPL Token account
+    pub liquidity_supply: Pubkey,
+    /// Reserve liquidity fee receiver SPL Token account
+    pub liquidity_fee_receiver: Pubkey,
+    /// Reserve collateral SPL Token mint
+    pub collateral_mint: Pubkey,
+    /// Reserve collateral supply SPL Token account
+    pub collateral_supply: Pubkey,
+    /// Un_coll_supply SPL Token account
+    pub un_coll_supply: Pubkey,
+}
+
+/// Keys of a market deployment
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeploymentKeys {
+    /// Lending market program id
+    pub program_id: Pubkey,
+    /// Lending market account
+    pub lending_market: Pubkey,
+    /// Lending market owner, also pays for the new accounts
+    pub owner: Pubkey,
+    /// Keys of the reserves to create, by liquidity mint
+    pub reserves: BTreeMap<Pubkey, ReserveKeys>,
+}
+
+/// Instructions deploying a market config, in execution order:
+///   - create and init the lending market
+///   - for each reserve marked `if_create`, by liquidity mint: create the reserve, supply,
+///     fee receiver, collateral mint, collateral supply and un-coll supply accounts, then
+///     init the reserve and set its pause flags if any is set
+///
+/// `InitReserve` does not carry the pause flags, so a `SetConfig` follows the `InitReserve` of
+/// paused entries in the same plan.
+pub fn plan_deployment(
+    config: &MarketConfig,
+    keys: &DeploymentKeys,
+    quote_currency: [u8; 32],
+    rent: &Rent,
+) -> Result<Vec<Instruction>, ConfigError> {
+    let mut instructions = vec![
+        create_account(
+            keys,
+            keys.lending_market,
+            LendingMarket::LEN,
+            keys.program_id,
+            rent,
+        ),
+        init_lending_market(
+            keys.program_id,
+            keys.owner,
+            quote_currency,
+            keys.lending_market,
+            config.oracle_program,
+            config.larix_oracle_program_id,
+            config.larix_oracle_id,
+        ),
+    ];
+    for (liquidity_mint, entry) in config.reserves_to_create() {
+        let reserve_keys = keys
+            .reserves
+            .get(liquidity_mint)
+            .ok_or_else(|| invalid_reserve(entry, "no keys passed in for the reserve accounts"))?;
+        plan_reserve(
+            config,
+            keys,
+            liquidity_mint,
+            entry,
+            reserve_keys,
+            rent,
+            &mut instructions,
+        )?;
+    }
+    Ok(instructions)
+}
+
+fn plan_reserve(
+    config: &MarketConfig,
+    keys: &DeploymentKeys,
+    liquidity_mint: &Pubkey,
+    entry: &ReserveEntry,
+    reserve_keys: &ReserveKeys,
+    rent: &Rent,
+    instructions: &mut Vec<Instruction>,
+) -> Result<(), ConfigError> {
+    let (pyth_product, pyth_price, larix_oracle) = if entry.is_lp {
+        let bridge_pool = entry.bridge_pool.clone().unwrap_or_default();
+        match (bridge_pool.pool, bridge_pool.lp_price) {
+            // InitReserve takes any account in the Pyth product slot of LP reserves
+            (Some(pool), Some(lp_price)) => (config.oracle_program, pool, lp_price),
+            _ => {
+                return Err(invalid_reserve(
+                    entry,
+                    "LP reserves need bridge pool accounts",
+                ))
+            }
+        }
+    } else {
+        // validated by MarketConfig::from_json
+        match (entry.product_data, entry.price, entry.larix_oracle_id) {
+            (Some(product), Some(price), Some(larix_oracle)) => (product, price, larix_oracle),
+            _ => return Err(invalid_reserve(entry, "missing oracle accounts")),
+        }
+    };
+
+    instructions.push(create_account(
+        keys,
+        reserve_keys.reserve,
+        Reserve::LEN,
+        keys.program_id,
+        rent,
+    ));
+    for token_account in [
+        reserve_keys.liquidity_supply,
+        reserve_keys.liquidity_fee_receiver,
+    ] {
+        instructions.push(create_account(
+            keys,
+            token_account,
+            spl_token::state::Account::LEN,
+            spl_token::id(),
+            rent,
+        ));
+    }
+    instructions.push(create_account(
+        keys,
+        reserve_keys.collateral_mint,
+        spl_token::state::Mint::LEN,
+        spl_token::id(),
+        rent,
+    ));
+    for token_account in [reserve_keys.collateral_supply, reserve_keys.un_coll_supply] {
+        instructions.push(create_account(
+            keys,
+            token_account,
+            spl_token::state::Account::LEN,
+            spl_token::id(),
+            rent,
+        ));
+    }
+
+    let use_pyth_oracle = entry
+        .use_pyth_oracle()
+        .ok_or_else(|| invalid_reserve(entry, "use_pyth_oracle is not set"))?;
+    let reserve_config = entry.reserve_config();
+    instructions.push(init_reserve(
+        keys.program_id,
+        reserve_config.clone(),
+        entry.total_mining_speed,
+        entry.kink_util_rate,
+        use_pyth_oracle,
+        entry.is_lp,
+        &InitReserveAccounts {
+            reserve: reserve_keys.reserve,
+            liquidity_mint: *liquidity_mint,
+            liquidity_supply: reserve_keys.liquidity_supply,
+            liquidity_fee_receiver: reserve_keys.liquidity_fee_receiver,
+            pyth_product,
+            pyth_price,
+            larix_oracle,
+            collateral_mint: reserve_keys.collateral_mint,
+            collateral_supply: reserve_keys.collateral_supply,
+            lending_market: keys.lending_market,
+            lending_market_owner: keys.owner,
+            un_coll_supply: reserve_keys.un_coll_supply,
+        },
+    ));
+    if entry.deposit_paused || entry.borrow_paused || entry.liquidation_paused {
+        instructions.push(set_config(
+            keys.program_id,
+            reserve_config,
+            reserve_keys.reserve,
+            keys.lending_market,
+            keys.owner,
+        ));
+    }
+    Ok(())
+}
+
+fn create_account(
+    keys: &DeploymentKeys,
+    new_account: Pubkey,
+    space: usize,
+    owner: Pubkey,
+    rent: &Rent,
+) -> Instruction {
+    system_instruction::create_account(
+        &keys.owner,
+        &new_account,
+        rent.minimum_balance(space),
+        space as u64,
+        &owner,
+    )
+}
+
+fn invalid_reserve(entry: &ReserveEntry, reason: &str) -> ConfigError {
+    ConfigError::InvalidReserve {
+        name: entry.name.clone(),
+        reason: reason.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::instruction::LendingInstruction;
+    use solana_program::system_program;
+
+    fn deployment_keys(config: &MarketConfig) -> DeploymentKeys {
+        DeploymentKeys {
+            program_id: Pubkey::new_unique(),
+            lending_market: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            reserves: config
+                .reserves_to_create()
+                .map(|(liquidity_mint, _)| {
+                    let reserve_keys = ReserveKeys {
+                        reserve: Pubkey::new_unique(),
+                        liquidity_supply: Pubkey::new_unique(),
+                        liquidity_fee_receiver: Pubkey::new_unique(),
+                        collateral_mint: Pubkey::new_unique(),
+                        collateral_supply: Pubkey::new_unique(),
+                        un_coll_supply: Pubkey::new_unique(),
+                    };
+                    (*liquidity_mint, reserve_keys)
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn plan_is_ordered_and_deterministic() {
+        let mut config =
+            MarketConfig::from_json(include_str!("../../config/config-test.json")).unwrap();
+        config.reserves.retain(|_, entry| !entry.is_lp);
+        for entry in config.reserves.values_mut() {
+            entry.use_pyth_oracle = Some(true);
+        }
+        config.reserves.values_mut().next().unwrap().if_create = true;
+        let keys = deployment_keys(&config);
+        let rent = Rent::default();
+
+        let plan = plan_deployment(&config, &keys, [0; 32], &rent).unwrap();
+        assert_eq!(
+            plan,
+            plan_deployment(&config, &keys, [0; 32], &rent).unwrap()
+        );
+
+        let creates = plan
+            .iter()
+            .filter(|instruction| instruction.program_id == system_program::id())
+            .count();
+        let reserves = config.reserves_to_create().count();
+        assert_eq!(creates, 1 + 6 * reserves);
+        assert_eq!(plan[0].accounts[1].pubkey, keys.lending_market);
+        assert!(matches!(
+            LendingInstruction::unpack(&plan[1].data),
+            Ok(LendingInstruction::InitLendingMarket { .. })
+        ));
+
+        // the whole InitReserve payload decodes back to the entry values
+        let init_reserve_data: Vec<_> = plan
+            .iter()
+            .filter(|instruction| instruction.program_id == keys.program_id)
+            .skip(1)
+            .map(|instruction| instruction.data.clone())
+            .collect();
+        let expected: Vec<_> = config
+            .reserves_to_create()
+            .map(|(_, entry)| LendingInstruction::InitReserve {
+                config: entry.reserve_config(),
+                total_mining_speed: entry.total_mining_speed,
+                kink_util_rate: entry.kink_util_rate,
+                use_pyth_oracle: true,
+                is_lp: false,
+            })
+            .collect();
+        let decoded: Vec<_> = init_reserve_data
+            .iter()
+            .map(|data| LendingInstruction::unpack(data).unwrap())
+            .collect();
+        assert_eq!(decoded, expected);
+        let repacked: Vec<_> = expected.iter().map(LendingInstruction::pack).collect();
+        assert_eq!(init_reserve_data, repacked);
+
+        // a paused entry is initialized, then paused
+        let mut paused = config.clone();
+        let (liquidity_mint, entry) = paused.reserves.iter_mut().next().unwrap();
+        entry.borrow_paused = true;
+        let (liquidity_mint, entry) = (*liquidity_mint, entry.clone());
+        let mut paused_plan = plan_deployment(&paused, &keys, [0; 32], &rent).unwrap();
+        let position = paused_plan
+            .iter()
+            .position(|instruction| instruction.data.first() == Some(&14))
+            .unwrap();
+        let set_config = paused_plan.remove(position);
+        assert_eq!(plan, paused_plan);
+        let init_reserve = &paused_plan[position - 1];
+        assert_eq!(
+            init_reserve.accounts[0].pubkey,
+            keys.reserves[&liquidity_mint].reserve
+        );
+        assert_eq!(
+            set_config.accounts[0].pubkey,
+            init_reserve.accounts[0].pubkey
+        );
+        assert_eq!(
+            LendingInstruction::unpack(&set_config.data),
+            Ok(LendingInstruction::SetConfig {
+                config: entry.reserve_config()
+            })
+        );
+        assert!(entry.reserve_config().borrow_paused);
+    }
+
+    #[test]
+    fn plan_requires_reserve_accounts() {
+        let mut config =
+            MarketConfig::from_json(include_str!("../../config/config-test.json")).unwrap();
+        let keys = deployment_keys(&config);
+        // LP entries of the test config have no bridge pool accounts yet
+        assert!(matches!(
+            plan_deployment(&config, &keys, [0; 32], &Rent::default()),
+            Err(ConfigError::InvalidReserve { .. })
+        ));
+
+        config.reserves.retain(|_, entry| !entry.is_lp);
+        // the test config does not say which oracle its reserves use
+        let keys = deployment_keys(&config);
+        assert!(matches!(
+            plan_deployment(&config, &keys, [0; 32], &Rent::default()),
+            Err(ConfigError::InvalidReserve { .. })
+        ));
+
+        for entry in config.reserves.values_mut() {
+            entry.use_pyth_oracle = Some(true);
+        }
+        assert!(plan_deployment(&config, &keys, [0; 32], &Rent::default()).is_ok());
+        let mut keys = deployment_keys(&config);
+        let liquidity_mint = *keys.reserves.keys().next().unwrap();
+        keys.reserves.remove(&liquidity_mint);
+        assert!(matches!(
+            plan_deployment(&config, &keys, [0; 32], &Rent::default()),
+            Err(ConfigError::InvalidReserve { .. })
+        ));
+    }
+}
@@ -1,228 +1,244 @@
 //! Error types
 
 use num_derive::FromPrimitive;
-use solana_program::{decode_error::DecodeError, program_error::ProgramError};
+use num_traits::FromPrimitive as _;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+    pubkey::Pubkey,
+};
+use std::convert::TryFrom;
 use thiserror::Error;
 
 /// Errors that may be returned by the TokenLending program.
+///
+/// Codes are part of the program interface and must never change: new variants take the
+/// next free code, and `error_codes.json` is regenerated with `error_code_table_json`.
 #[derive(Clone, Debug, Eq, Error, FromPrimitive, PartialEq)]
 pub enum LendingError {
     // 0
     /// Invalid instruction data passed in.
     #[error("Failed to unpack instruction data")]
-    InstructionUnpackError,
+    InstructionUnpackError = 0,
     #[error("Failed to unpack config instruction data")]
-    ConfigInstructionUnpackError,
+    ConfigInstructionUnpackError = 1,
     /// The account cannot be initialized because it is already in use.
     #[error("Account is already initialized")]
-    AlreadyInitialized,
+    AlreadyInitialized = 2,
     /// Lamport balance below rent-exempt threshold.
     #[error("Lamport balance below rent-exempt threshold")]
-    NotRentExempt,
+    NotRentExempt = 3,
     /// The program address provided doesn't match the value generated by the program.
     #[error("Market authority is invalid")]
-    InvalidMarketAuthority,
+    InvalidMarketAuthority = 4,
 
     // 5
     /// The owner of the input isn't set to the program address generated by the program.
     #[error("Input account owner is not the program address")]
-    InvalidAccountOwner,
+    InvalidAccountOwner = 5,
     /// The owner of the account input isn't set to the correct token program id.
     #[error("Input token account is not owned by the correct token program id")]
-    InvalidTokenOwner,
+    InvalidTokenOwner = 6,
     /// Expected an SPL Token account
     #[error("Input token account is not valid")]
-    InvalidTokenAccount,
+    InvalidTokenAccount = 7,
     /// Expected an SPL Token mint
     #[error("Input token mint account is not valid")]
-    InvalidTokenMint,
+    InvalidTokenMint = 8,
     /// Expected a different SPL Token program
     #[error("Input token program account is not valid")]
-    InvalidTokenProgram,
+    InvalidTokenProgram = 9,
 
     // 10
     /// Invalid amount, must be greater than zero
     #[error("Input amount is invalid")]
-    InvalidAmount,
+    InvalidAmount = 10,
     /// Invalid config value
     #[error("Input config value is invalid")]
-    InvalidConfig,
+    InvalidConfig = 11,
     /// Invalid config value
     #[error("Input account must be a signer")]
-    InvalidSigner,
+    InvalidSigner = 12,
     /// Invalid account input
     #[error("Invalid account input")]
-    InvalidAccountInput,
+    InvalidAccountInput = 13,
     /// Math operation overflow
     #[error("Math operation overflow")]
-    MathOverflow,
+    MathOverflow = 14,
 
     // 15
     /// Token initialize mint failed
     #[error("Token initialize mint failed")]
-    TokenInitializeMintFailed,
+    TokenInitializeMintFailed = 15,
     /// Token initialize account failed
     #[error("Token initialize account failed")]
-    TokenInitializeAccountFailed,
+    TokenInitializeAccountFailed = 16,
     /// Token transfer failed
     #[error("Token transfer failed")]
-    TokenTransferFailed,
+    TokenTransferFailed = 17,
     /// Token mint to failed
     #[error("Token mint to failed")]
-    TokenMintToFailed,
+    TokenMintToFailed = 18,
     /// Token burn failed
     #[error("Token burn failed")]
-    TokenBurnFailed,
+    TokenBurnFailed = 19,
 
     // 20
     /// Insufficient liquidity available
     #[error("Insufficient liquidity available")]
-    InsufficientLiquidity,
+    InsufficientLiquidity = 20,
     /// This reserve's collateral cannot be used for borrows
     #[error("Input reserve has collateral disabled")]
-    ReserveCollateralDisabled,
+    ReserveCollateralDisabled = 21,
     /// Reserve state stale
     #[error("Reserve state needs to be refreshed")]
-    ReserveStale,
+    ReserveStale = 22,
     /// Withdraw amount too small
     #[error("Withdraw amount too small")]
-    WithdrawTooSmall,
+    WithdrawTooSmall = 23,
     /// Withdraw amount too large
     #[error("Withdraw amount too large")]
-    WithdrawTooLarge,
+    WithdrawTooLarge = 24,
 
     // 25
     /// Borrow amount too small
     #[error("Borrow amount too small to receive liquidity after fees")]
-    BorrowTooSmall,
+    BorrowTooSmall = 25,
     /// Borrow amount too large
     #[error("Borrow amount too large for deposited collateral")]
-    BorrowTooLarge,
+    BorrowTooLarge = 26,
     /// Repay amount too small
     #[error("Repay amount too small to transfer liquidity")]
-    RepayTooSmall,
+    RepayTooSmall = 27,
     /// Liquidation amount too small
     #[error("Liquidation amount too small to receive collateral")]
-    LiquidationTooSmall,
+    LiquidationTooSmall = 28,
     /// Cannot liquidate healthy obligations
     #[error("Cannot liquidate healthy obligations")]
-    ObligationHealthy,
+    ObligationHealthy = 29,
 
     // 30
     /// Obligation state stale
     #[error("Obligation state needs to be refreshed")]
-    ObligationStale,
+    ObligationStale = 30,
     /// Obligation reserve limit exceeded
     #[error("Obligation reserve limit exceeded")]
-    ObligationReserveLimit,
+    ObligationReserveLimit = 31,
     /// Expected a different obligation owner
     #[error("Obligation owner is invalid")]
-    InvalidObligationOwner,
+    InvalidObligationOwner = 32,
     /// Obligation deposits are empty
     #[error("Obligation deposits are empty")]
-    ObligationDepositsEmpty,
+    ObligationDepositsEmpty = 33,
     /// Obligation borrows are empty
     #[error("Obligation borrows are empty")]
-    ObligationBorrowsEmpty,
+    ObligationBorrowsEmpty = 34,
 
     // 35
     /// Obligation deposits have zero value
     #[error("Obligation deposits have zero value")]
-    ObligationDepositsZero,
+    ObligationDepositsZero = 35,
     /// Obligation borrows have zero value
     #[error("Obligation borrows have zero value")]
-    ObligationBorrowsZero,
+    ObligationBorrowsZero = 36,
     /// Invalid obligation collateral
     #[error("Invalid obligation collateral")]
-    InvalidObligationCollateral,
+    InvalidObligationCollateral = 37,
     /// Invalid obligation liquidity
     #[error("Invalid obligation liquidity")]
-    InvalidObligationLiquidity,
+    InvalidObligationLiquidity = 38,
     /// Obligation collateral is empty
     #[error("Obligation collateral is empty")]
-    ObligationCollateralEmpty,
+    ObligationCollateralEmpty = 39,
 
     // 40
     /// Obligation liquidity is empty
     #[error("Obligation liquidity is empty")]
-    ObligationLiquidityEmpty,
+    ObligationLiquidityEmpty = 40,
     /// Negative interest rate
     #[error("Interest rate is negative")]
-    NegativeInterestRate,
+    NegativeInterestRate = 41,
     /// Oracle config is invalid
     #[error("Input oracle config is invalid")]
-    InvalidOracleConfig,
+    InvalidOracleConfig = 42,
     /// Expected a different flash loan receiver program
     #[error("Input flash loan receiver program account is not valid")]
-    InvalidFlashLoanReceiverProgram,
+    InvalidFlashLoanReceiverProgram = 43,
     /// Not enough liquidity after flash loan
     #[error("Not enough liquidity after flash loan")]
-    NotEnoughLiquidityAfterFlashLoan,
+    NotEnoughLiquidityAfterFlashLoan = 44,
 
     // 45
     #[error("Market config err")]
-    MarketConfigErr,
+    MarketConfigErr = 45,
     #[error("Mining reserve empty")]
-    MiningReserveEmpty,
+    MiningReserveEmpty = 46,
     #[error("Invalid mining reserve err")]
-    InvalidMiningReserve,
+    InvalidMiningReserve = 47,
     #[error("Mining volume limit")]
-    MiningVolumeLimit,
+    MiningVolumeLimit = 48,
     #[error("Mining reserve repeat")]
-    MiningReserveRepeat,
+    MiningReserveRepeat = 49,
 
     //50
     #[error("Mining state needs to be refreshed")]
-    MiningStale,
+    MiningStale = 50,
     #[error("Provided account is not for this L-token")]
-    LTokenMismatch,
+    LTokenMismatch = 51,
     #[error("Invalid mining owner")]
-    InvalidMininigOwner,
+    InvalidMininigOwner = 52,
     #[error("Mining-mining Reserve mismatch")]
-    MiningMiningReserveMismatch,
+    MiningMiningReserveMismatch = 53,
     #[error("Obligation-mining Reserve mismatch")]
-    ObligationMiningReserveMismatch,
+    ObligationMiningReserveMismatch = 54,
 
     // 55
     /// Expected a different market owner
     #[error("Market owner is invalid")]
-    InvalidMarketOwner,
+    InvalidMarketOwner = 55,
     #[error("Deposit amount too large for account balance")]
-    DepositAmountTooLarge,
+    DepositAmountTooLarge = 56,
     #[error("Redeem amount too large for deposit balance")]
-    RedeemAmountTooLarge,
+    RedeemAmountTooLarge = 57,
     #[error("Withdraw amount too large for deposit balance")]
-    WithdrawAmountTooLarge,
+    WithdrawAmountTooLarge = 58,
     #[error("Repay amount too large for borrow amount")]
-    RepayAmountTooLarge,
+    RepayAmountTooLarge = 59,
 
     //60
     #[error("Deposit is now paused")]
-    DepositPaused,
+    DepositPaused = 60,
     #[error("Borrow is now paused")]
-    BorrowPaused,
+    BorrowPaused = 61,
     #[error("Liquidation is now paused")]
-    LiquidationPaused,
+    LiquidationPaused = 62,
     #[error("Can not withdraw all deposit")]
-    CanNotWithdrawAllDeposit,
+    CanNotWithdrawAllDeposit = 63,
     #[error("Can not reentry")]
-    ReentryLocked,
+    ReentryLocked = 64,
 
     //65
     #[error("Wrong host fee receiver")]
-    WrongHostFeeReceiver,
+    WrongHostFeeReceiver = 65,
     #[error("Host fee receivers full")]
-    HostFeeReceiversFull,
+    HostFeeReceiversFull = 66,
     #[error("Host fee receiver not fund")]
-    HostFeeReceiverNotFund,
+    HostFeeReceiverNotFund = 67,
     #[error("Exceed reserve deposit limit")]
-    ExceedReserveDepositLimit,
+    ExceedReserveDepositLimit = 68,
     #[error("Bridge is stale")]
-    BridgeStale,
+    BridgeStale = 69,
 
     // 70
     #[error("No This Attribute")]
-    NoThisAttribute,
+    NoThisAttribute = 70,
+    #[error("Oracle prices diverge")]
+    OraclePriceDivergence = 71,
+    #[error("Market price moved too much")]
+    PriceMoveTooLarge = 72,
+    #[error("Oracle price confidence interval is too wide")]
+    OracleConfidenceTooWide = 73,
 
 }
 
@@ -237,3 +253,263 @@ impl<T> DecodeError<T> for LendingError {
         "Lending Error"
     }
 }
+
+impl TryFrom<u32> for LendingError {
+    type Error = ProgramError;
+
+    fn try_from(code: u32) -> Result<Self, Self::Error> {
+        Self::from_u32(code).ok_or(ProgramError::InvalidArgument)
+    }
+}
+
+impl PrintProgramError for LendingError {
+    fn print<E>(&self)
+    where
+        E: 'static
+            + std::error::Error
+            + DecodeError<E>
+            + PrintProgramError
+            + num_traits::FromPrimitive,
+    {
+        msg!("Error: {}", self);
+    }
+}
+
+/// Log text between the program id and the hex code of a custom program error
+const CUSTOM_PROGRAM_ERROR: &str = " failed: custom program error: 0x";
+
+impl LendingError {
+    /// Last variant, new variants must update it
+    pub const LAST: Self = Self::OracleConfidenceTooWide;
+
+    /// Parse a lending error from a transaction log line
+    /// "Program <program_id> failed: custom program error: 0x1a"
+    ///
+    /// Failures of other programs, such as the token program called by the lending program,
+    /// are not lending errors and return `None`.
+    pub fn from_log(program_id: &Pubkey, log: &str) -> Option<Self> {
+        let log = log.strip_prefix("Program ")?;
+        let (id, hex) = log.split_at(log.find(CUSTOM_PROGRAM_ERROR)?);
+        if id != program_id.to_string() {
+            return None;
+        }
+        let code = u32::from_str_radix(&hex[CUSTOM_PROGRAM_ERROR.len()..], 16).ok()?;
+        Self::try_from(code).ok()
+    }
+
+    /// Find the first lending error of a program in transaction logs
+    pub fn from_logs<S: AsRef<str>>(program_id: &Pubkey, logs: &[S]) -> Option<Self> {
+        logs.iter()
+            .find_map(|log| Self::from_log(program_id, log.as_ref()))
+    }
+}
+
+/// Entry of the lending error code table
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErrorCode {
+    /// Custom program error code
+    pub code: u32,
+    /// Variant name
+    pub name: String,
+    /// Error message
+    pub message: String,
+}
+
+/// All lending errors, ordered by code
+pub fn error_code_table() -> Vec<ErrorCode> {
+    (0..=LendingError::LAST as u32)
+        .filter_map(|code| LendingError::try_from(code).ok())
+        .map(|error| ErrorCode {
+            code: error.clone() as u32,
+            name: format!("{:?}", error),
+            message: error.to_string(),
+        })
+        .collect()
+}
+
+/// Lending error code table as a JSON array of `{"code", "name", "message"}` objects
+pub fn error_code_table_json() -> String {
+    let entries: Vec<String> = error_code_table()
+        .iter()
+        .map(|entry| {
+            format!(
+                "  {{\"code\": {}, \"name\": \"{}\", \"message\": \"{}\"}}",
+                entry.code,
+                entry.name,
+                entry.message.replace('\\', "\\\\").replace('"', "\\\"")
+            )
+        })
+        .collect();
+    format!("[\n{}\n]\n", entries.join(",\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn error_codes_are_pinned() {
+        // Codes clients rely on, never change or remove an entry
+        let pinned = [
+            (0, "InstructionUnpackError"),
+            (1, "ConfigInstructionUnpackError"),
+            (2, "AlreadyInitialized"),
+            (3, "NotRentExempt"),
+            (4, "InvalidMarketAuthority"),
+            (5, "InvalidAccountOwner"),
+            (6, "InvalidTokenOwner"),
+            (7, "InvalidTokenAccount"),
+            (8, "InvalidTokenMint"),
+            (9, "InvalidTokenProgram"),
+            (10, "InvalidAmount"),
+            (11, "InvalidConfig"),
+            (12, "InvalidSigner"),
+            (13, "InvalidAccountInput"),
+            (14, "MathOverflow"),
+            (15, "TokenInitializeMintFailed"),
+            (16, "TokenInitializeAccountFailed"),
+            (17, "TokenTransferFailed"),
+            (18, "TokenMintToFailed"),
+            (19, "TokenBurnFailed"),
+            (20, "InsufficientLiquidity"),
+            (21, "ReserveCollateralDisabled"),
+            (22, "ReserveStale"),
+            (23, "WithdrawTooSmall"),
+            (24, "WithdrawTooLarge"),
+            (25, "BorrowTooSmall"),
+            (26, "BorrowTooLarge"),
+            (27, "RepayTooSmall"),
+            (28, "LiquidationTooSmall"),
+            (29, "ObligationHealthy"),
+            (30, "ObligationStale"),
+            (31, "ObligationReserveLimit"),
+            (32, "InvalidObligationOwner"),
+            (33, "ObligationDepositsEmpty"),
+            (34, "ObligationBorrowsEmpty"),
+            (35, "ObligationDepositsZero"),
+            (36, "ObligationBorrowsZero"),
+            (37, "InvalidObligationCollateral"),
+            (38, "InvalidObligationLiquidity"),
+            (39, "ObligationCollateralEmpty"),
+            (40, "ObligationLiquidityEmpty"),
+            (41, "NegativeInterestRate"),
+            (42, "InvalidOracleConfig"),
+            (43, "InvalidFlashLoanReceiverProgram"),
+            (44, "NotEnoughLiquidityAfterFlashLoan"),
+            (45, "MarketConfigErr"),
+            (46, "MiningReserveEmpty"),
+            (47, "InvalidMiningReserve"),
+            (48, "MiningVolumeLimit"),
+            (49, "MiningReserveRepeat"),
+            (50, "MiningStale"),
+            (51, "LTokenMismatch"),
+            (52, "InvalidMininigOwner"),
+            (53, "MiningMiningReserveMismatch"),
+            (54, "ObligationMiningReserveMismatch"),
+            (55, "InvalidMarketOwner"),
+            (56, "DepositAmountTooLarge"),
+            (57, "RedeemAmountTooLarge"),
+            (58, "WithdrawAmountTooLarge"),
+            (59, "RepayAmountTooLarge"),
+            (60, "DepositPaused"),
+            (61, "BorrowPaused"),
+            (62, "LiquidationPaused"),
+            (63, "CanNotWithdrawAllDeposit"),
+            (64, "ReentryLocked"),
+            (65, "WrongHostFeeReceiver"),
+            (66, "HostFeeReceiversFull"),
+            (67, "HostFeeReceiverNotFund"),
+            (68, "ExceedReserveDepositLimit"),
+            (69, "BridgeStale"),
+            (70, "NoThisAttribute"),
+            (71, "OraclePriceDivergence"),
+            (72, "PriceMoveTooLarge"),
+            (73, "OracleConfidenceTooWide"),
+        ];
+        for (code, name) in pinned.iter() {
+            let error = LendingError::try_from(*code).unwrap();
+            assert_eq!(format!("{:?}", error), *name, "code {} changed", code);
+        }
+        assert_eq!(error_code_table().len(), pinned.len());
+        assert_eq!(LendingError::LAST as usize, pinned.len() - 1);
+        assert!(
+            LendingError::try_from(LendingError::LAST as u32 + 1).is_err(),
+            "LendingError::LAST is not the last variant"
+        );
+    }
+
+    #[test]
+    fn error_code_table_is_exported() {
+        assert_eq!(error_code_table_json(), include_str!("../error_codes.json"));
+    }
+
+    #[test]
+    fn error_from_code() {
+        assert_eq!(LendingError::try_from(0), Ok(LendingError::InstructionUnpackError));
+        assert_eq!(
+            LendingError::try_from(LendingError::BridgeStale as u32),
+            Ok(LendingError::BridgeStale)
+        );
+        assert_eq!(LendingError::try_from(u32::MAX), Err(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn error_from_logs() {
+        let program_id = Pubkey::new_unique();
+        let failed = format!("Program {} failed: custom program error: 0x1a", program_id);
+        let logs = [
+            "Program log: Instruction: Borrow Obligation Liquidity",
+            &failed,
+        ];
+        let error = LendingError::from_logs(&program_id, &logs).unwrap();
+        assert_eq!(error.clone() as u32, 0x1a);
+        assert_eq!(ProgramError::from(error), ProgramError::Custom(0x1a));
+        assert_eq!(
+            LendingError::from_log(
+                &program_id,
+                &format!("Program {} failed: custom program error: 0x45", program_id)
+            ),
+            Some(LendingError::BridgeStale)
+        );
+        assert_eq!(
+            LendingError::from_log(
+                &program_id,
+                &format!(
+                    "Program {} failed: custom program error: 0xffff",
+                    program_id
+                )
+            ),
+            None
+        );
+        assert_eq!(
+            LendingError::from_log(&program_id, "failed: custom program error: 0x45"),
+            None
+        );
+        assert_eq!(LendingError::from_logs(&program_id, &logs[..1]), None);
+        assert_eq!(LendingError::from_logs(&Pubkey::new_unique(), &logs), None);
+    }
+
+    #[test]
+    fn token_program_errors_are_not_lending_errors() {
+        let program_id = Pubkey::new_unique();
+        // spl-token InsufficientFunds, 0x1 is also a lending error code
+        let logs = [
+            format!("Program {} invoke [1]", program_id),
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [2]".to_string(),
+            "Program log: Error: insufficient funds".to_string(),
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA failed: custom program error: 0x1"
+                .to_string(),
+        ];
+        assert_eq!(LendingError::from_logs(&program_id, &logs), None);
+
+        let mut logs = logs.to_vec();
+        logs.push(format!(
+            "Program {} failed: custom program error: 0x11",
+            program_id
+        ));
+        assert_eq!(
+            LendingError::from_logs(&program_id, &logs),
+            Some(LendingError::TokenTransferFailed)
+        );
+    }
+}
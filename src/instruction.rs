@@ -4,11 +4,13 @@
 use std::mem::size_of;
 use crate::{
     error::LendingError,
+    state::reserve::{init_reserve_accounts_index, ReserveConfig, ReserveFees},
 };
 use solana_program::{
     msg,
     program_error::ProgramError,
     pubkey::Pubkey,
+    sysvar,
 };
 use solana_program::instruction::{AccountMeta, Instruction};
 use solana_program::pubkey::PUBKEY_BYTES;
@@ -89,7 +91,9 @@ pub enum LendingInstruction {
     ///   14 `[]` Token program id.
 
     InitReserve {
-        /// Reserve configuration values
+        /// Reserve configuration values, pause flags and deposit limit are set by SetConfig
+        config: ReserveConfig,
+        /// Mine emitted by the reserve per slot
         total_mining_speed: u64,
         kink_util_rate: u64,
         use_pyth_oracle:bool,
@@ -335,9 +339,21 @@ pub enum LendingInstruction {
         call_back_data: Vec<u8>
     },
     // 14
+    /// Updates the config of a reserve.
     ///
+    /// The payload is the InitReserve config values followed by the pause flags and the
+    /// deposit limit, in `ReserveConfig` field order. It has not been checked against a
+    /// transaction of the deployed program.
     ///
-    SetConfig ,
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Reserve account.
+    ///   1. `[]` Lending market account.
+    ///   2. `[signer]` Lending market owner.
+    SetConfig {
+        /// Reserve configuration values, with the pause flags and deposit limit
+        config: ReserveConfig,
+    },
     // 16
     /// 0. `[]` Mining account
     /// 1. `[signer]` Mining owner
@@ -496,22 +512,13 @@ impl LendingInstruction {
                 Self::SetLendingMarketOwner { new_owner }
             }
             2 => {
-                let (_optimal_utilization_rate, rest) = unpack_u8(rest)?;
-                let (_loan_to_value_ratio, rest) = unpack_u8(rest)?;
-                let (_liquidation_bonus, rest) = unpack_u8(rest)?;
-                let (_liquidation_threshold, rest) = unpack_u8(rest)?;
-                let (_min_borrow_rate, rest) = unpack_u8(rest)?;
-                let (_optimal_borrow_rate, rest) = unpack_u8(rest)?;
-                let (_max_borrow_ratse, rest) = unpack_u8(rest)?;
-                let (_borrow_fee_wad, rest) = unpack_u64(rest)?;
-                let (_reserve_owner_fee_wad, rest) = unpack_u64(rest)?;
-                let (_flash_loan_fee_wad, rest) = unpack_u64(rest)?;
-                let (_host_fee_percentage, rest) = unpack_u8(rest)?;
+                let (config, rest) = unpack_reserve_config(rest)?;
                 let (total_mining_speed,rest) = unpack_u64(rest)?;
                 let (kink_util_rate,rest) = unpack_u64(rest)?;
                 let (use_pyth_oracle,rest) = unpack_bool(rest)?;
                 let (is_lp,_rest) = unpack_bool(rest)?;
                 Self::InitReserve {
+                    config,
                     total_mining_speed,
                     kink_util_rate,
                     use_pyth_oracle,
@@ -556,7 +563,16 @@ impl LendingInstruction {
                 Self::FlashLoan { amount ,call_back_data}
             }
             14 => {
-                Self::SetConfig
+                let (mut config, rest) = unpack_reserve_config(rest)?;
+                let (deposit_paused, rest) = unpack_bool(rest)?;
+                let (borrow_paused, rest) = unpack_bool(rest)?;
+                let (liquidation_paused, rest) = unpack_bool(rest)?;
+                let (deposit_limit, _rest) = unpack_u64(rest)?;
+                config.deposit_paused = deposit_paused;
+                config.borrow_paused = borrow_paused;
+                config.liquidation_paused = liquidation_paused;
+                config.deposit_limit = deposit_limit;
+                Self::SetConfig { config }
             }
             16 => {
                 Self::InitMining
@@ -609,6 +625,36 @@ impl LendingInstruction {
     pub fn pack(&self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(size_of::<Self>());
         match *self {
+            Self::InitLendingMarket {
+                owner,
+                quote_currency,
+            } => {
+                buf.push(0);
+                buf.extend_from_slice(owner.as_ref());
+                buf.extend_from_slice(quote_currency.as_ref());
+            }
+            Self::InitReserve {
+                ref config,
+                total_mining_speed,
+                kink_util_rate,
+                use_pyth_oracle,
+                is_lp,
+            } => {
+                buf.push(2);
+                pack_reserve_config(config, &mut buf);
+                buf.extend_from_slice(&total_mining_speed.to_le_bytes());
+                buf.extend_from_slice(&kink_util_rate.to_le_bytes());
+                buf.push(use_pyth_oracle as u8);
+                buf.push(is_lp as u8);
+            }
+            Self::SetConfig { ref config } => {
+                buf.push(14);
+                pack_reserve_config(config, &mut buf);
+                buf.push(config.deposit_paused as u8);
+                buf.push(config.borrow_paused as u8);
+                buf.push(config.liquidation_paused as u8);
+                buf.extend_from_slice(&config.deposit_limit.to_le_bytes());
+            }
             Self::RefreshReserves => {
                 buf.push(24)
             }
@@ -656,6 +702,174 @@ impl LendingInstruction {
         buf
     }
 }
+
+/// Rate and fee values of a reserve config, as packed in InitReserve and SetConfig
+fn unpack_reserve_config(input: &[u8]) -> Result<(ReserveConfig, &[u8]), ProgramError> {
+    let (optimal_utilization_rate, rest) = unpack_u8(input)?;
+    let (loan_to_value_ratio, rest) = unpack_u8(rest)?;
+    let (liquidation_bonus, rest) = unpack_u8(rest)?;
+    let (liquidation_threshold, rest) = unpack_u8(rest)?;
+    let (min_borrow_rate, rest) = unpack_u8(rest)?;
+    let (optimal_borrow_rate, rest) = unpack_u8(rest)?;
+    let (max_borrow_rate, rest) = unpack_u8(rest)?;
+    let (borrow_fee_wad, rest) = unpack_u64(rest)?;
+    let (reserve_owner_fee_wad, rest) = unpack_u64(rest)?;
+    let (flash_loan_fee_wad, rest) = unpack_u64(rest)?;
+    let (host_fee_percentage, rest) = unpack_u8(rest)?;
+    let config = ReserveConfig {
+        optimal_utilization_rate,
+        loan_to_value_ratio,
+        liquidation_bonus,
+        liquidation_threshold,
+        min_borrow_rate,
+        optimal_borrow_rate,
+        max_borrow_rate,
+        fees: ReserveFees {
+            borrow_fee_wad,
+            reserve_owner_fee_wad,
+            flash_loan_fee_wad,
+            host_fee_percentage,
+            host_fee_receivers: vec![],
+        },
+        ..ReserveConfig::default()
+    };
+    Ok((config, rest))
+}
+
+fn pack_reserve_config(config: &ReserveConfig, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&[
+        config.optimal_utilization_rate,
+        config.loan_to_value_ratio,
+        config.liquidation_bonus,
+        config.liquidation_threshold,
+        config.min_borrow_rate,
+        config.optimal_borrow_rate,
+        config.max_borrow_rate,
+    ]);
+    buf.extend_from_slice(&config.fees.borrow_fee_wad.to_le_bytes());
+    buf.extend_from_slice(&config.fees.reserve_owner_fee_wad.to_le_bytes());
+    buf.extend_from_slice(&config.fees.flash_loan_fee_wad.to_le_bytes());
+    buf.push(config.fees.host_fee_percentage);
+}
+
+/// Creates an 'InitLendingMarket' instruction.
+pub fn init_lending_market(
+    program_id: Pubkey,
+    owner: Pubkey,
+    quote_currency: [u8; 32],
+    lending_market_pubkey: Pubkey,
+    oracle_program_id: Pubkey,
+    larix_oracle_program_id: Pubkey,
+    larix_oracle_id: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(owner, true),
+            AccountMeta::new(lending_market_pubkey, false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(oracle_program_id, false),
+            AccountMeta::new_readonly(larix_oracle_program_id, false),
+            AccountMeta::new_readonly(larix_oracle_id, false),
+        ],
+        data: LendingInstruction::InitLendingMarket {
+            owner,
+            quote_currency,
+        }
+        .pack(),
+    }
+}
+
+/// Accounts of an 'InitReserve' instruction
+pub struct InitReserveAccounts {
+    /// Reserve account - uninitialized
+    pub reserve: Pubkey,
+    /// Reserve liquidity SPL Token mint
+    pub liquidity_mint: Pubkey,
+    /// Reserve liquidity supply SPL Token account
+    pub liquidity_supply: Pubkey,
+    /// Reserve liquidity fee receiver
+    pub liquidity_fee_receiver: Pubkey,
+    /// Pyth product account, any account when is_lp is true
+    pub pyth_product: Pubkey,
+    /// Pyth price account, BridgePool account when is_lp is true
+    pub pyth_price: Pubkey,
+    /// Larix oracle price account, LpPrice account when is_lp is true
+    pub larix_oracle: Pubkey,
+    /// Reserve collateral SPL Token mint
+    pub collateral_mint: Pubkey,
+    /// Reserve collateral token supply
+    pub collateral_supply: Pubkey,
+    /// Lending market account
+    pub lending_market: Pubkey,
+    /// Lending market owner
+    pub lending_market_owner: Pubkey,
+    /// Un_coll_supply_account
+    pub un_coll_supply: Pubkey,
+}
+
+/// Creates an 'InitReserve' instruction.
+pub fn init_reserve(
+    program_id: Pubkey,
+    config: ReserveConfig,
+    total_mining_speed: u64,
+    kink_util_rate: u64,
+    use_pyth_oracle: bool,
+    is_lp: bool,
+    accounts: &InitReserveAccounts,
+) -> Instruction {
+    use init_reserve_accounts_index as index;
+    let mut metas = vec![AccountMeta::new_readonly(Pubkey::default(), false); index::TOKEN_PROGRAM_ID + 1];
+    metas[index::RESERVE_ACCOUNT] = AccountMeta::new(accounts.reserve, false);
+    metas[index::LIQUIDITY_MINT] = AccountMeta::new_readonly(accounts.liquidity_mint, false);
+    metas[index::LIQUIDITY_SUPPLY] = AccountMeta::new(accounts.liquidity_supply, false);
+    metas[index::LIQUIDITY_FEE_RECEIVER] = AccountMeta::new(accounts.liquidity_fee_receiver, false);
+    metas[index::PYTH_PRODUCT] = AccountMeta::new_readonly(accounts.pyth_product, false);
+    metas[index::PYTH_PRICE] = AccountMeta::new_readonly(accounts.pyth_price, false);
+    metas[index::LARIX_ORACLE] = AccountMeta::new_readonly(accounts.larix_oracle, false);
+    metas[index::COLLATERAL_MINT] = AccountMeta::new(accounts.collateral_mint, false);
+    metas[index::COLLATERAL_SUPPLY] = AccountMeta::new(accounts.collateral_supply, false);
+    metas[index::LENDING_MARKET] = AccountMeta::new_readonly(accounts.lending_market, false);
+    metas[index::LENDING_MARKET_OWNER] =
+        AccountMeta::new_readonly(accounts.lending_market_owner, true);
+    metas[index::UN_COLL_SUPPLY] = AccountMeta::new(accounts.un_coll_supply, false);
+    metas[index::CLOCK_SYSVAR] = AccountMeta::new_readonly(sysvar::clock::id(), false);
+    metas[index::RENT_SYSVAR] = AccountMeta::new_readonly(sysvar::rent::id(), false);
+    metas[index::TOKEN_PROGRAM_ID] = AccountMeta::new_readonly(spl_token::id(), false);
+    Instruction {
+        program_id,
+        accounts: metas,
+        data: LendingInstruction::InitReserve {
+            config,
+            total_mining_speed,
+            kink_util_rate,
+            use_pyth_oracle,
+            is_lp,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a 'SetConfig' instruction.
+pub fn set_config(
+    program_id: Pubkey,
+    config: ReserveConfig,
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(reserve_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(lending_market_owner, true),
+        ],
+        data: LendingInstruction::SetConfig { config }.pack(),
+    }
+}
+
 pub fn refresh_reserves(
     program_id:Pubkey,
     reserves:Vec<Pubkey>,
@@ -11,6 +11,44 @@ pub const HALF_WAD: u64 = 500_000_000_000_000_000;
 /// Scale for percentages
 pub const PERCENT_SCALER: u64 = 10_000_000_000_000_000;
 
+/// Direction to round a result in when it cannot be represented exactly
+///
+/// `TryMul` and `TryDiv` truncate, which rounds down. Protocol computations should instead
+/// round in the protocol's favor:
+///   - amounts owed to the protocol, such as fees, borrows and repayments, round up
+///   - amounts paid out or credited by the protocol, such as withdrawals, collateral values
+///     and liquidation collateral, round down
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round toward zero
+    Floor,
+    /// Round away from zero
+    Ceiling,
+}
+
+/// Digits of a decimal string such as "1.25", scaled by a WAD
+///
+/// Fails with `InvalidArgument` if the string is not digits with an optional fractional
+/// part of at most `SCALE` digits.
+pub(crate) fn parse_scaled_digits(s: &str) -> Result<impl Iterator<Item = u64> + '_, ProgramError> {
+    let (integer, fraction) = match s.split_once('.') {
+        Some((integer, fraction)) if !fraction.is_empty() => (integer, fraction),
+        Some(_) => return Err(ProgramError::InvalidArgument),
+        None => (s, ""),
+    };
+    if integer.is_empty()
+        || fraction.len() > SCALE
+        || !integer.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit())
+    {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(integer
+        .bytes()
+        .chain(fraction.bytes())
+        .chain((fraction.len()..SCALE).map(|_| b'0'))
+        .map(|b| (b - b'0') as u64))
+}
+
 /// Try to subtract, return an error on underflow
 pub trait TrySub: Sized {
     /// Subtract
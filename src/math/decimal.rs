@@ -17,7 +17,7 @@ use crate::{
     math::{common::*, Rate},
 };
 use solana_program::program_error::ProgramError;
-use std::{convert::TryFrom, fmt};
+use std::{convert::TryFrom, fmt, str::FromStr};
 use uint::construct_uint;
 
 // U192 with 192 bits consisting of 3 x 64-bit words
@@ -25,29 +25,32 @@ construct_uint! {
     pub struct U192(3);
 }
 
+/// WAD as a U192, built from its words to avoid a conversion per use
+const WAD_U192: U192 = U192([WAD, 0, 0]);
+/// HALF_WAD as a U192
+const HALF_WAD_U192: U192 = U192([HALF_WAD, 0, 0]);
+
 /// Large decimal values, precise to 18 digits
 #[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Eq, Ord)]
 pub struct Decimal(pub U192);
 
 impl Decimal {
     /// One
-    pub fn one() -> Self {
-        Self(Self::wad())
+    pub const fn one() -> Self {
+        Self(WAD_U192)
     }
 
     /// Zero
-    pub fn zero() -> Self {
-        Self(U192::zero())
+    pub const fn zero() -> Self {
+        Self(U192([0, 0, 0]))
     }
 
-    // OPTIMIZE: use const slice when fixed in BPF toolchain
-    fn wad() -> U192 {
-        U192::from(WAD)
+    const fn wad() -> U192 {
+        WAD_U192
     }
 
-    // OPTIMIZE: use const slice when fixed in BPF toolchain
-    fn half_wad() -> U192 {
-        U192::from(HALF_WAD)
+    const fn half_wad() -> U192 {
+        HALF_WAD_U192
     }
 
     /// Create scaled decimal from percent value
@@ -96,6 +99,108 @@ impl Decimal {
             .ok_or(LendingError::MathOverflow)?;
         Ok(u64::try_from(ceil_val).map_err(|_| LendingError::MathOverflow)?)
     }
+
+    /// Square root, rounded down
+    pub fn try_sqrt(&self) -> Result<Self, ProgramError> {
+        let radicand = self
+            .0
+            .checked_mul(Self::wad())
+            .ok_or(LendingError::MathOverflow)?;
+        if radicand < U192::from(2u64) {
+            return Ok(Self(radicand));
+        }
+        let mut root = U192::one() << (radicand.bits() / 2 + 1);
+        loop {
+            let next = (root + radicand / root) >> 1;
+            if next >= root {
+                return Ok(Self(root));
+            }
+            root = next;
+        }
+    }
+
+    /// Multiply, rounding the result in the given direction
+    pub fn try_mul_rounded(
+        self,
+        rhs: impl Into<Self>,
+        rounding: Rounding,
+    ) -> Result<Self, ProgramError> {
+        let rhs: Self = rhs.into();
+        let product = self
+            .0
+            .checked_mul(rhs.0)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(Self(div_rounded(product, Self::wad(), rounding)?))
+    }
+
+    /// Multiply, rounding the result down
+    pub fn try_mul_floor(self, rhs: impl Into<Self>) -> Result<Self, ProgramError> {
+        self.try_mul_rounded(rhs, Rounding::Floor)
+    }
+
+    /// Multiply, rounding the result up
+    pub fn try_mul_ceil(self, rhs: impl Into<Self>) -> Result<Self, ProgramError> {
+        self.try_mul_rounded(rhs, Rounding::Ceiling)
+    }
+
+    /// Divide, rounding the result in the given direction
+    pub fn try_div_rounded(
+        self,
+        rhs: impl Into<Self>,
+        rounding: Rounding,
+    ) -> Result<Self, ProgramError> {
+        let rhs: Self = rhs.into();
+        let dividend = self
+            .0
+            .checked_mul(Self::wad())
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(Self(div_rounded(dividend, rhs.0, rounding)?))
+    }
+
+    /// Divide, rounding the result down
+    pub fn try_div_floor(self, rhs: impl Into<Self>) -> Result<Self, ProgramError> {
+        self.try_div_rounded(rhs, Rounding::Floor)
+    }
+
+    /// Divide, rounding the result up
+    pub fn try_div_ceil(self, rhs: impl Into<Self>) -> Result<Self, ProgramError> {
+        self.try_div_rounded(rhs, Rounding::Ceiling)
+    }
+
+    /// Round to `precision` fractional digits in the given direction
+    pub fn try_round_to(&self, precision: usize, rounding: Rounding) -> Result<Self, ProgramError> {
+        if precision >= SCALE {
+            return Ok(*self);
+        }
+        let unit = U192::exp10(SCALE - precision);
+        let rounded = div_rounded(self.0, unit, rounding)?
+            .checked_mul(unit)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(Self(rounded))
+    }
+
+    /// Round down to `precision` fractional digits
+    pub fn try_floor(&self, precision: usize) -> Result<Self, ProgramError> {
+        self.try_round_to(precision, Rounding::Floor)
+    }
+
+    /// Round up to `precision` fractional digits
+    pub fn try_ceil(&self, precision: usize) -> Result<Self, ProgramError> {
+        self.try_round_to(precision, Rounding::Ceiling)
+    }
+}
+
+/// Divide, rounding the quotient in the given direction
+fn div_rounded(dividend: U192, divisor: U192, rounding: Rounding) -> Result<U192, ProgramError> {
+    let quotient = dividend
+        .checked_div(divisor)
+        .ok_or(LendingError::MathOverflow)?;
+    if rounding == Rounding::Ceiling && quotient * divisor != dividend {
+        return Ok(quotient
+            .checked_add(U192::one())
+            .ok_or(LendingError::MathOverflow)?);
+    }
+    Ok(quotient)
 }
 
 impl fmt::Display for Decimal {
@@ -111,6 +216,37 @@ impl fmt::Display for Decimal {
     }
 }
 
+impl FromStr for Decimal {
+    type Err = ProgramError;
+
+    /// Parse a decimal string such as "1.25" exactly, with at most 18 fractional digits
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut scaled_val = U192::zero();
+        for digit in parse_scaled_digits(s)? {
+            scaled_val = scaled_val
+                .checked_mul(U192::from(10u64))
+                .and_then(|val| val.checked_add(U192::from(digit)))
+                .ok_or(LendingError::MathOverflow)?;
+        }
+        Ok(Self(scaled_val))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Decimal {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Decimal {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 impl From<u64> for Decimal {
     fn from(val: u64) -> Self {
         Self(Self::wad() * U192::from(val))
@@ -207,13 +343,154 @@ impl TryMul<Decimal> for Decimal {
 #[cfg(test)]
 mod test {
     use super::*;
+    use proptest::prelude::*;
+
+    construct_uint! {
+        pub struct U256(4);
+    }
+
+    fn narrow(val: U256) -> Option<U192> {
+        if val.bits() > 192 {
+            return None;
+        }
+        Some(U192([val.0[0], val.0[1], val.0[2]]))
+    }
+
+    fn widen(val: Decimal) -> U256 {
+        U256([val.0 .0[0], val.0 .0[1], val.0 .0[2], 0])
+    }
 
     #[test]
     fn test_scaler() {
         assert_eq!(U192::exp10(SCALE), Decimal::wad());
+        assert_eq!(U192::from(WAD), Decimal::wad());
+        assert_eq!(U192::from(HALF_WAD), Decimal::half_wad());
+    }
+
+    // Differential tests against 256-bit reference arithmetic over the packed 16-byte range
+    proptest! {
+        #[test]
+        fn mul_matches_reference(a in any::<u128>(), b in any::<u128>()) {
+            let (a, b) = (Decimal::from_scaled_val(a), Decimal::from_scaled_val(b));
+            let expected = narrow(widen(a) * widen(b))
+                .map(|product| Decimal(product / U192::from(WAD)))
+                .ok_or_else(|| LendingError::MathOverflow.into());
+            prop_assert_eq!(a.try_mul(b), expected);
+        }
+
+        #[test]
+        fn div_matches_reference(a in any::<u128>(), b in any::<u128>()) {
+            let (a, b) = (Decimal::from_scaled_val(a), Decimal::from_scaled_val(b));
+            let expected = if b == Decimal::zero() {
+                Err(LendingError::MathOverflow.into())
+            } else {
+                Ok(Decimal(narrow(widen(a) * U256::from(WAD) / widen(b)).unwrap()))
+            };
+            prop_assert_eq!(a.try_div(b), expected);
+        }
+
+        #[test]
+        fn add_sub_match_reference(a in any::<u128>(), b in any::<u128>()) {
+            let (a, b) = (Decimal::from_scaled_val(a), Decimal::from_scaled_val(b));
+            prop_assert_eq!(a.try_add(b), Ok(Decimal(narrow(widen(a) + widen(b)).unwrap())));
+            let expected = if a >= b {
+                Ok(Decimal(narrow(widen(a) - widen(b)).unwrap()))
+            } else {
+                Err(LendingError::MathOverflow.into())
+            };
+            prop_assert_eq!(a.try_sub(b), expected);
+        }
+
+        #[test]
+        fn round_matches_reference(a in any::<u128>(), val in any::<u64>()) {
+            let decimal = Decimal::from_scaled_val(a);
+            let to_u64 = |val: U256| {
+                if val.bits() > 64 {
+                    Err(LendingError::MathOverflow.into())
+                } else {
+                    Ok(val.low_u64())
+                }
+            };
+            let (wad, half_wad) = (U256::from(WAD), U256::from(HALF_WAD));
+            prop_assert_eq!(decimal.try_round_u64(), to_u64((U256::from(a) + half_wad) / wad));
+            prop_assert_eq!(decimal.try_ceil_u64(), to_u64((U256::from(a) + wad - 1) / wad));
+            prop_assert_eq!(decimal.try_floor_u64(), to_u64(U256::from(a) / wad));
+            prop_assert_eq!(widen(Decimal::from(val)), U256::from(val) * wad);
+        }
     }
     #[test]
     fn test_one() {
         assert_eq!(Decimal::one(),Decimal(Decimal::wad()))
     }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("1.25".parse(), Ok(Decimal::from_scaled_val(1_250_000_000_000_000_000)));
+        assert_eq!("0.000000000000000001".parse(), Ok(Decimal::from_scaled_val(1)));
+        assert_eq!("18446744073709551615".parse(), Ok(Decimal::from(u64::MAX)));
+        let decimal = Decimal::from_scaled_val(123_456_789_000_000_000_001);
+        assert_eq!(decimal.to_string().parse(), Ok(decimal));
+
+        for malformed in ["", ".5", "1.", "-1", "1.2.3", "1e3", " 1", "0.0000000000000000001"] {
+            assert_eq!(malformed.parse::<Decimal>(), Err(ProgramError::InvalidArgument));
+        }
+        assert_eq!(
+            "1".repeat(60).parse::<Decimal>(),
+            Err(LendingError::MathOverflow.into())
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize() {
+        use serde::{de::IntoDeserializer, Deserialize};
+        let deserializer: serde::de::value::StrDeserializer<serde::de::value::Error> =
+            "2.5".into_deserializer();
+        assert_eq!(
+            Decimal::deserialize(deserializer),
+            Ok(Decimal::from_scaled_val(2_500_000_000_000_000_000))
+        );
+        let deserializer: serde::de::value::StrDeserializer<serde::de::value::Error> =
+            "2,5".into_deserializer();
+        assert!(Decimal::deserialize(deserializer).is_err());
+    }
+
+    #[test]
+    fn test_rounding() {
+        let third = Decimal::one().try_div(3u64).unwrap();
+        assert_eq!(
+            Decimal::one().try_div_floor(3u64),
+            Ok(Decimal::from_scaled_val(333_333_333_333_333_333))
+        );
+        assert_eq!(
+            Decimal::one().try_div_ceil(3u64),
+            Ok(Decimal::from_scaled_val(333_333_333_333_333_334))
+        );
+        assert_eq!(third.try_mul_floor(third), third.try_mul(third));
+        assert_eq!(
+            third.try_mul_ceil(third),
+            Ok(Decimal::from_scaled_val(111_111_111_111_111_111))
+        );
+        assert_eq!(Decimal::from(2u64).try_mul_ceil(3u64), Ok(Decimal::from(6u64)));
+
+        assert_eq!(third.try_floor(2), Ok(Decimal::from_scaled_val(330_000_000_000_000_000)));
+        assert_eq!(third.try_ceil(2), Ok(Decimal::from_scaled_val(340_000_000_000_000_000)));
+        assert_eq!(third.try_ceil(0), Ok(Decimal::one()));
+        assert_eq!(Decimal::one().try_ceil(0), Ok(Decimal::one()));
+        assert_eq!(third.try_ceil(18), Ok(third));
+    }
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(Decimal::zero().try_sqrt().unwrap(), Decimal::zero());
+        assert_eq!(Decimal::from(4u64).try_sqrt().unwrap(), Decimal::from(2u64));
+        assert_eq!(
+            Decimal::from(2u64).try_sqrt().unwrap(),
+            Decimal::from_scaled_val(1_414_213_562_373_095_048)
+        );
+        assert_eq!(
+            Decimal::from_scaled_val(1).try_sqrt().unwrap(),
+            Decimal::from_scaled_val(1_000_000_000)
+        );
+    }
 }
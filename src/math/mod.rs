@@ -3,8 +3,10 @@
 mod common;
 mod decimal;
 mod rate;
+mod signed_decimal;
 
 
 pub use common::*;
 pub use decimal::*;
 pub use rate::*;
+pub use signed_decimal::*;
@@ -23,7 +23,7 @@ use crate::{
     math::{common::*, decimal::Decimal},
 };
 use solana_program::program_error::ProgramError;
-use std::{convert::TryFrom, fmt};
+use std::{convert::TryFrom, fmt, str::FromStr};
 use uint::construct_uint;
 
 // U128 with 128 bits consisting of 2 x 64-bit words
@@ -31,29 +31,78 @@ construct_uint! {
     pub struct U128(2);
 }
 
+/// WAD as a U128, built from its words to avoid a conversion per use
+const WAD_U128: U128 = U128([WAD, 0]);
+/// HALF_WAD as a U128
+const HALF_WAD_U128: U128 = U128([HALF_WAD, 0]);
+
+/// Max number of binomial terms summed by `Rate::try_pow_approx`
+pub const MAX_POW_APPROX_TERMS: u64 = 32;
+
+/// Scaled values of `y = (base - 1) * exp` below which the binomial term `n + 1` of
+/// `base^exp` truncates to zero: the largest `y` with `y^(n + 1) / (n + 1)! < 10^-18`, rounded down
+const POW_APPROX_TERM_BOUNDS: [u64; MAX_POW_APPROX_TERMS as usize] = [
+    1,
+    1_414_213_562,
+    1_817_120_592_832,
+    69_992_710_231_611,
+    654_389_389_941_237,
+    2_993_795_165_523_908,
+    9_067_552_455_410_089,
+    21_168_499_046_056_743,
+    41_471_662_743_969_128,
+    71_775_512_682_996_096,
+    113_409_783_095_179_164,
+    167_248_185_088_871_560,
+    233_771_085_557_052_068,
+    313_143_537_746_612_038,
+    405_290_702_258_938_784,
+    509_963_067_277_853_111,
+    626_789_449_228_875_076,
+    755_318_386_410_680_580,
+    895_049_563_963_754_354,
+    1_045_457_134_810_505_887,
+    1_206_006_670_931_599_279,
+    1_376_167_219_722_088_923,
+    1_555_419_659_464_673_558,
+    1_743_262_292_379_240_156,
+    1_939_214_398_750_459_590,
+    2_142_818_302_595_673_066,
+    2_353_640_363_693_270_662,
+    2_571_271_206_261_336_789,
+    2_795_325_414_930_732_921,
+    3_025_440_868_444_020_208,
+    3_261_277_836_243_559_085,
+    3_502_517_929_222_270_238,
+];
+
+/// Number of `Rate` multiplications done by `Rate::try_pow`, each a U128 multiplication and
+/// division
+pub fn pow_cost(exp: u64) -> u64 {
+    (u64::BITS - exp.leading_zeros() + (exp >> 1).count_ones()) as u64
+}
+
 /// Small decimal values, precise to 18 digits
 #[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Eq, Ord)]
 pub struct Rate(pub U128);
 
 impl Rate {
     /// One
-    pub fn one() -> Self {
-        Self(Self::wad())
+    pub const fn one() -> Self {
+        Self(WAD_U128)
     }
 
     /// Zero
-    pub fn zero() -> Self {
-        Self(U128::from(0))
+    pub const fn zero() -> Self {
+        Self(U128([0, 0]))
     }
 
-    // OPTIMIZE: use const slice when fixed in BPF toolchain
-    fn wad() -> U128 {
-        U128::from(WAD)
+    const fn wad() -> U128 {
+        WAD_U128
     }
 
-    // OPTIMIZE: use const slice when fixed in BPF toolchain
-    fn half_wad() -> U128 {
-        U128::from(HALF_WAD)
+    const fn half_wad() -> U128 {
+        HALF_WAD_U128
     }
 
     /// Create scaled decimal from percent value
@@ -82,6 +131,18 @@ impl Rate {
         Ok(u64::try_from(rounded_val).map_err(|_| LendingError::MathOverflow)?)
     }
 
+    /// Ceiling scaled rate to u64
+    pub fn try_ceil_u64(&self) -> Result<u64, ProgramError> {
+        let ceil_val = div_rounded(self.0, Self::wad(), Rounding::Ceiling)?;
+        Ok(u64::try_from(ceil_val).map_err(|_| LendingError::MathOverflow)?)
+    }
+
+    /// Floor scaled rate to u64
+    pub fn try_floor_u64(&self) -> Result<u64, ProgramError> {
+        let floor_val = div_rounded(self.0, Self::wad(), Rounding::Floor)?;
+        Ok(u64::try_from(floor_val).map_err(|_| LendingError::MathOverflow)?)
+    }
+
     /// Calculates base^exp
     pub fn try_pow(&self, mut exp: u64) -> Result<Rate, ProgramError> {
         let mut base = *self;
@@ -102,6 +163,132 @@ impl Rate {
 
         Ok(ret)
     }
+
+    /// Calculates base^exp for a base of at least one, approximating `try_pow` with the
+    /// binomial expansion `(1 + x)^exp = sum(C(exp, k) * x^k)`, summed until a term truncates
+    /// to zero
+    ///
+    /// Each term costs two U128 multiplications and divisions, a `try_pow` step one. The
+    /// expansion is only used when its bounded number of terms costs less than `try_pow`,
+    /// see `pow_approx_terms`, which holds for short slot gaps and low rates. Otherwise, or
+    /// for a base below one, `try_pow` is used.
+    ///
+    /// For up to 250% per year compounded per slot over up to a year, the result agrees with
+    /// `try_pow` within 10^-10 relative to the result, as checked by the tests.
+    pub fn try_pow_approx(&self, exp: u64) -> Result<Rate, ProgramError> {
+        if let Some(terms) = self.pow_approx_terms(exp) {
+            if let Some(pow) = self.try_pow_binomial(exp, terms)? {
+                return Ok(pow);
+            }
+        }
+        self.try_pow(exp)
+    }
+
+    /// Max number of binomial terms computed by `try_pow_approx` for base^exp, `None` if they
+    /// would cost as much as `try_pow`
+    pub fn pow_approx_terms(&self, exp: u64) -> Option<u64> {
+        if *self < Self::one() {
+            return None;
+        }
+        let y = self.try_sub(Self::one()).ok()?.try_mul(exp).ok()?;
+        let terms = POW_APPROX_TERM_BOUNDS
+            .iter()
+            .position(|bound| y.0 < U128::from(*bound))? as u64
+            + 1;
+        if 2 * terms < pow_cost(exp) {
+            Some(terms)
+        } else {
+            None
+        }
+    }
+
+    /// Binomial expansion of base^exp, `None` if no term up to `terms` truncates to zero
+    fn try_pow_binomial(&self, exp: u64, terms: u64) -> Result<Option<Rate>, ProgramError> {
+        let x = self.try_sub(Self::one())?;
+        let mut sum = Self::one();
+        let mut term = Self::one();
+        for k in 1..=terms {
+            term = term.try_mul(exp - k + 1)?.try_div(k)?.try_mul(x)?;
+            if term == Self::zero() {
+                return Ok(Some(sum));
+            }
+            sum = sum.try_add(term)?;
+        }
+        Ok(None)
+    }
+
+    /// Multiply, rounding the result in the given direction
+    pub fn try_mul_rounded(self, rhs: Self, rounding: Rounding) -> Result<Self, ProgramError> {
+        let product = self
+            .0
+            .checked_mul(rhs.0)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(Self(div_rounded(product, Self::wad(), rounding)?))
+    }
+
+    /// Multiply, rounding the result down
+    pub fn try_mul_floor(self, rhs: Self) -> Result<Self, ProgramError> {
+        self.try_mul_rounded(rhs, Rounding::Floor)
+    }
+
+    /// Multiply, rounding the result up
+    pub fn try_mul_ceil(self, rhs: Self) -> Result<Self, ProgramError> {
+        self.try_mul_rounded(rhs, Rounding::Ceiling)
+    }
+
+    /// Divide, rounding the result in the given direction
+    pub fn try_div_rounded(self, rhs: Self, rounding: Rounding) -> Result<Self, ProgramError> {
+        let dividend = self
+            .0
+            .checked_mul(Self::wad())
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(Self(div_rounded(dividend, rhs.0, rounding)?))
+    }
+
+    /// Divide, rounding the result down
+    pub fn try_div_floor(self, rhs: Self) -> Result<Self, ProgramError> {
+        self.try_div_rounded(rhs, Rounding::Floor)
+    }
+
+    /// Divide, rounding the result up
+    pub fn try_div_ceil(self, rhs: Self) -> Result<Self, ProgramError> {
+        self.try_div_rounded(rhs, Rounding::Ceiling)
+    }
+
+    /// Round to `precision` fractional digits in the given direction
+    pub fn try_round_to(&self, precision: usize, rounding: Rounding) -> Result<Self, ProgramError> {
+        if precision >= SCALE {
+            return Ok(*self);
+        }
+        let unit = U128::exp10(SCALE - precision);
+        let rounded = div_rounded(self.0, unit, rounding)?
+            .checked_mul(unit)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(Self(rounded))
+    }
+
+    /// Round down to `precision` fractional digits
+    pub fn try_floor(&self, precision: usize) -> Result<Self, ProgramError> {
+        self.try_round_to(precision, Rounding::Floor)
+    }
+
+    /// Round up to `precision` fractional digits
+    pub fn try_ceil(&self, precision: usize) -> Result<Self, ProgramError> {
+        self.try_round_to(precision, Rounding::Ceiling)
+    }
+}
+
+/// Divide, rounding the quotient in the given direction
+fn div_rounded(dividend: U128, divisor: U128, rounding: Rounding) -> Result<U128, ProgramError> {
+    let quotient = dividend
+        .checked_div(divisor)
+        .ok_or(LendingError::MathOverflow)?;
+    if rounding == Rounding::Ceiling && quotient * divisor != dividend {
+        return Ok(quotient
+            .checked_add(U128::one())
+            .ok_or(LendingError::MathOverflow)?);
+    }
+    Ok(quotient)
 }
 
 impl fmt::Display for Rate {
@@ -117,6 +304,37 @@ impl fmt::Display for Rate {
     }
 }
 
+impl FromStr for Rate {
+    type Err = ProgramError;
+
+    /// Parse a decimal string such as "1.25" exactly, with at most 18 fractional digits
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut scaled_val = U128::zero();
+        for digit in parse_scaled_digits(s)? {
+            scaled_val = scaled_val
+                .checked_mul(U128::from(10u64))
+                .and_then(|val| val.checked_add(U128::from(digit)))
+                .ok_or(LendingError::MathOverflow)?;
+        }
+        Ok(Self(scaled_val))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Rate {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Rate {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 impl TryFrom<Decimal> for Rate {
     type Error = ProgramError;
     fn try_from(decimal: Decimal) -> Result<Self, Self::Error> {
@@ -191,9 +409,174 @@ impl TryMul<Rate> for Rate {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::math::U192;
+    use proptest::prelude::*;
+
+    fn narrow(val: U192) -> Option<U128> {
+        if val.bits() > 128 {
+            return None;
+        }
+        Some(U128([val.0[0], val.0[1]]))
+    }
+
+    #[test]
+    fn wad() {
+        assert_eq!(U128::from(WAD), Rate::wad());
+        assert_eq!(U128::from(HALF_WAD), Rate::half_wad());
+    }
+
+    // Differential tests against 192-bit reference arithmetic
+    proptest! {
+        #[test]
+        fn mul_div_match_reference(a in 0..1u128 << 96, b in 0..1u128 << 96) {
+            let (rate_a, rate_b) = (Rate(U128::from(a)), Rate(U128::from(b)));
+            let (a, b, wad) = (U192::from(a), U192::from(b), U192::from(WAD));
+            let expected = narrow(a * b)
+                .map(|product| Rate(product / U128::from(WAD)))
+                .ok_or_else(|| LendingError::MathOverflow.into());
+            prop_assert_eq!(rate_a.try_mul(rate_b), expected);
+
+            let expected = if b.is_zero() {
+                Err(LendingError::MathOverflow.into())
+            } else {
+                narrow(a * wad)
+                    .map(|dividend| Rate(dividend / narrow(b).unwrap()))
+                    .ok_or_else(|| LendingError::MathOverflow.into())
+            };
+            prop_assert_eq!(rate_a.try_div(rate_b), expected);
+        }
+    }
 
     #[test]
     fn checked_pow() {
         assert_eq!(Rate::one(), Rate::one().try_pow(u64::MAX).unwrap());
     }
+
+    const SLOTS_PER_YEAR: u64 = 78_840_000;
+
+    fn slot_base(percent_per_year: u8) -> Rate {
+        let rate_per_slot = Rate::from_percent(percent_per_year)
+            .try_div(SLOTS_PER_YEAR)
+            .unwrap();
+        Rate::one().try_add(rate_per_slot).unwrap()
+    }
+
+    /// Check `try_pow_approx` against `try_pow` for a rate per year compounded per slot
+    fn check_pow_approx(percent_per_year: u8, slots: u64) -> Result<(), String> {
+        let max_relative_error = Rate::from_scaled_val(100_000_000);
+        let base = slot_base(percent_per_year);
+        if let Some(terms) = base.pow_approx_terms(slots) {
+            if base.try_pow_binomial(slots, terms) == Ok(None) {
+                return Err(format!(
+                    "{}% per year over {} slots: no zero term within {} terms",
+                    percent_per_year, slots, terms
+                ));
+            }
+        }
+        let exact = base.try_pow(slots).unwrap();
+        let approx = base.try_pow_approx(slots).unwrap();
+        let error = if approx > exact {
+            approx.try_sub(exact).unwrap()
+        } else {
+            exact.try_sub(approx).unwrap()
+        };
+        if error > exact.try_mul(max_relative_error).unwrap() {
+            return Err(format!(
+                "{}% per year over {} slots: {} != {}",
+                percent_per_year, slots, approx, exact
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn pow_approx_matches_pow() {
+        for percent_per_year in [1u8, 10, 50, 100, 250] {
+            let mut gaps = vec![0, 1, 2, 3, 10, 150, 9_000, SLOTS_PER_YEAR / 12, SLOTS_PER_YEAR];
+            gaps.extend((0..26).map(|shift| (1u64 << shift) + 7));
+            for gap in gaps {
+                check_pow_approx(percent_per_year, gap).unwrap();
+            }
+        }
+        let base = Rate::one().try_add(Rate::from_percent(1)).unwrap();
+        for exp in 0..=MAX_POW_APPROX_TERMS {
+            assert_eq!(base.try_pow_approx(exp), base.try_pow(exp));
+        }
+        assert_eq!(Rate::from_percent(50).try_pow_approx(3), Rate::from_percent(50).try_pow(3));
+    }
+
+    #[test]
+    fn pow_approx_costs_less_than_pow() {
+        // try_pow squares once per bit and multiplies once per set bit but the lowest one
+        for (exp, cost) in [(0, 0), (1, 1), (2, 3), (3, 3), (150, 12)] {
+            assert_eq!(pow_cost(exp), cost);
+        }
+        for percent_per_year in [1u8, 10, 50, 100, 250] {
+            let base = slot_base(percent_per_year);
+            let mut gaps = vec![0, 1, 2, 3, 10, 150, 9_000, SLOTS_PER_YEAR / 12, SLOTS_PER_YEAR];
+            gaps.extend((0..27).map(|shift| 1u64 << shift));
+            gaps.extend((0..27).map(|shift| (1u64 << shift) - 1));
+            for gap in gaps {
+                // each binomial term costs two try_pow steps
+                let cost = base
+                    .pow_approx_terms(gap)
+                    .map_or(pow_cost(gap), |terms| 2 * terms);
+                assert!(
+                    cost <= pow_cost(gap),
+                    "{}% per year over {} slots",
+                    percent_per_year,
+                    gap
+                );
+            }
+            // gaps between refreshes of up to an hour use the expansion
+            for gap in [150, 9_000] {
+                assert!(base.pow_approx_terms(gap).is_some());
+            }
+        }
+        // a year at a high rate does not
+        assert_eq!(slot_base(250).pow_approx_terms(SLOTS_PER_YEAR), None);
+    }
+
+    proptest! {
+        #[test]
+        fn pow_approx_within_bound(percent_per_year in 0..=250u8, slots in 0..=SLOTS_PER_YEAR) {
+            prop_assert_eq!(check_pow_approx(percent_per_year, slots), Ok(()));
+        }
+    }
+
+    #[test]
+    fn rounding() {
+        let rate = Rate::from_scaled_val(1_500_000_000_000_000_001);
+        assert_eq!(rate.try_floor_u64(), Ok(1));
+        assert_eq!(rate.try_ceil_u64(), Ok(2));
+        assert_eq!(Rate::one().try_ceil_u64(), Ok(1));
+        assert_eq!(rate.try_floor(1), Ok(Rate::from_percent(150)));
+        assert_eq!(rate.try_ceil(1), Ok(Rate::from_percent(160)));
+
+        let third = Rate::one().try_div_floor(Rate::from_percent(3)).unwrap();
+        assert_eq!(third, Rate(U128::from(33_333_333_333_333_333_333u128)));
+        assert_eq!(
+            Rate::one().try_div_ceil(Rate::from_percent(3)),
+            Ok(Rate(U128::from(33_333_333_333_333_333_334u128)))
+        );
+        assert_eq!(
+            Rate::from_scaled_val(1).try_mul_ceil(Rate::from_percent(50)),
+            Ok(Rate::from_scaled_val(1))
+        );
+        assert_eq!(
+            Rate::from_scaled_val(1).try_mul_floor(Rate::from_percent(50)),
+            Ok(Rate::zero())
+        );
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("0.05".parse(), Ok(Rate::from_percent(5)));
+        assert_eq!("0.000000000000000001".parse(), Ok(Rate::from_scaled_val(1)));
+        assert_eq!("1.".parse::<Rate>(), Err(ProgramError::InvalidArgument));
+        assert_eq!(
+            "1000000000000000000000".parse::<Rate>(),
+            Err(LendingError::MathOverflow.into())
+        );
+    }
 }
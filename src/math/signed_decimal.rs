@@ -0,0 +1,206 @@
+//! Signed decimal values for quantities such as PnL, price deltas and health changes.
+//!
+//! Signed decimals are stored as a sign and a [Decimal](struct.Decimal.html) magnitude,
+//! so they share its WAD scale and range. Zero is never negative.
+
+use crate::{
+    error::LendingError,
+    math::{common::*, Decimal},
+};
+use solana_program::program_error::ProgramError;
+use std::{cmp::Ordering, convert::TryFrom, fmt, ops::Neg};
+
+/// Signed decimal values, precise to 18 digits
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SignedDecimal {
+    negative: bool,
+    magnitude: Decimal,
+}
+
+impl SignedDecimal {
+    /// Create a signed decimal from a magnitude and a sign
+    pub fn new(magnitude: Decimal, negative: bool) -> Self {
+        Self {
+            negative: negative && magnitude != Decimal::zero(),
+            magnitude,
+        }
+    }
+
+    /// One
+    pub fn one() -> Self {
+        Self::from(Decimal::one())
+    }
+
+    /// Zero
+    pub fn zero() -> Self {
+        Self::default()
+    }
+
+    /// Signed difference `lhs - rhs` of two decimals
+    pub fn difference(lhs: Decimal, rhs: Decimal) -> Result<Self, ProgramError> {
+        if lhs >= rhs {
+            Ok(Self::new(lhs.try_sub(rhs)?, false))
+        } else {
+            Ok(Self::new(rhs.try_sub(lhs)?, true))
+        }
+    }
+
+    /// True if the value is below zero
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Absolute value
+    pub fn abs(&self) -> Decimal {
+        self.magnitude
+    }
+}
+
+impl fmt::Display for SignedDecimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negative {
+            f.write_str("-")?;
+        }
+        fmt::Display::fmt(&self.magnitude, f)
+    }
+}
+
+impl From<Decimal> for SignedDecimal {
+    fn from(val: Decimal) -> Self {
+        Self::new(val, false)
+    }
+}
+
+impl TryFrom<SignedDecimal> for Decimal {
+    type Error = ProgramError;
+
+    fn try_from(val: SignedDecimal) -> Result<Self, Self::Error> {
+        if val.negative {
+            return Err(LendingError::MathOverflow.into());
+        }
+        Ok(val.magnitude)
+    }
+}
+
+impl Neg for SignedDecimal {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(self.magnitude, !self.negative)
+    }
+}
+
+impl Ord for SignedDecimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, false) => self.magnitude.cmp(&other.magnitude),
+            (true, true) => other.magnitude.cmp(&self.magnitude),
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+        }
+    }
+}
+
+impl PartialOrd for SignedDecimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl TryAdd for SignedDecimal {
+    fn try_add(self, rhs: Self) -> Result<Self, ProgramError> {
+        if self.negative == rhs.negative {
+            return Ok(Self::new(self.magnitude.try_add(rhs.magnitude)?, self.negative));
+        }
+        let difference = Self::difference(self.magnitude, rhs.magnitude)?;
+        if self.negative {
+            Ok(-difference)
+        } else {
+            Ok(difference)
+        }
+    }
+}
+
+impl TrySub for SignedDecimal {
+    fn try_sub(self, rhs: Self) -> Result<Self, ProgramError> {
+        self.try_add(-rhs)
+    }
+}
+
+impl TryMul<u64> for SignedDecimal {
+    fn try_mul(self, rhs: u64) -> Result<Self, ProgramError> {
+        Ok(Self::new(self.magnitude.try_mul(rhs)?, self.negative))
+    }
+}
+
+impl TryMul<Decimal> for SignedDecimal {
+    fn try_mul(self, rhs: Decimal) -> Result<Self, ProgramError> {
+        Ok(Self::new(self.magnitude.try_mul(rhs)?, self.negative))
+    }
+}
+
+impl TryMul<SignedDecimal> for SignedDecimal {
+    fn try_mul(self, rhs: Self) -> Result<Self, ProgramError> {
+        Ok(Self::new(
+            self.magnitude.try_mul(rhs.magnitude)?,
+            self.negative != rhs.negative,
+        ))
+    }
+}
+
+impl TryDiv<u64> for SignedDecimal {
+    fn try_div(self, rhs: u64) -> Result<Self, ProgramError> {
+        Ok(Self::new(self.magnitude.try_div(rhs)?, self.negative))
+    }
+}
+
+impl TryDiv<Decimal> for SignedDecimal {
+    fn try_div(self, rhs: Decimal) -> Result<Self, ProgramError> {
+        Ok(Self::new(self.magnitude.try_div(rhs)?, self.negative))
+    }
+}
+
+impl TryDiv<SignedDecimal> for SignedDecimal {
+    fn try_div(self, rhs: Self) -> Result<Self, ProgramError> {
+        Ok(Self::new(
+            self.magnitude.try_div(rhs.magnitude)?,
+            self.negative != rhs.negative,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn signed(val: i64) -> SignedDecimal {
+        SignedDecimal::new(Decimal::from(val.unsigned_abs()), val < 0)
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(signed(3).try_sub(signed(5)), Ok(signed(-2)));
+        assert_eq!(signed(-3).try_add(signed(5)), Ok(signed(2)));
+        assert_eq!(signed(-3).try_sub(signed(5)), Ok(signed(-8)));
+        assert_eq!(signed(-3).try_add(signed(3)), Ok(SignedDecimal::zero()));
+        assert_eq!(signed(-3).try_mul(signed(-4)), Ok(signed(12)));
+        assert_eq!(signed(12).try_div(signed(-4)), Ok(signed(-3)));
+        assert_eq!(signed(-12).try_div(4u64), Ok(signed(-3)));
+        assert_eq!(-SignedDecimal::zero(), SignedDecimal::zero());
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(
+            SignedDecimal::difference(Decimal::from(1u64), Decimal::from(3u64)),
+            Ok(signed(-2))
+        );
+        assert_eq!(Decimal::try_from(signed(2)), Ok(Decimal::from(2u64)));
+        assert_eq!(
+            Decimal::try_from(signed(-2)),
+            Err(LendingError::MathOverflow.into())
+        );
+        assert_eq!(signed(-2).to_string(), "-2.000000000000000000");
+        assert!(signed(-3) < signed(-2) && signed(-2) < signed(1));
+    }
+}
@@ -1,8 +1,11 @@
 use crate::error::LendingError;
-use solana_program::{clock::Slot, program_error::ProgramError};
-use std::cmp::Ordering;
+use solana_program::{
+    clock::{Clock, Slot, UnixTimestamp},
+    program_error::ProgramError,
+};
+use std::{cmp::Ordering, convert::TryFrom};
 
-/// Number of slots to consider stale after
+/// Number of slots to consider stale after, unless configured by the reserve or lending market
 pub const STALE_AFTER_SLOTS_ELAPSED: u64 = 1;
 
 /// Last update state
@@ -12,12 +15,19 @@ pub struct LastUpdate {
     pub slot: Slot,
     /// True when marked stale, false when slot updated
     pub stale: bool,
+    /// Unix timestamp of the last update, 0 if never recorded
+    /// Only stored by reserves, for timestamp based interest accrual
+    pub unix_timestamp: UnixTimestamp,
 }
 
 impl LastUpdate {
     /// Create new last update
     pub fn new(slot: Slot) -> Self {
-        Self { slot, stale: true }
+        Self {
+            slot,
+            stale: true,
+            unix_timestamp: 0,
+        }
     }
 
     /// Return slots elapsed since given slot
@@ -28,20 +38,40 @@ impl LastUpdate {
         Ok(slots_elapsed)
     }
 
+    /// Return seconds elapsed since given unix timestamp, `None` if no timestamp was recorded
+    /// yet. A unix timestamp behind the recorded one counts as no time elapsed.
+    pub fn seconds_elapsed(&self, unix_timestamp: UnixTimestamp) -> Option<u64> {
+        if self.unix_timestamp == 0 {
+            return None;
+        }
+        let seconds_elapsed = unix_timestamp.saturating_sub(self.unix_timestamp);
+        Some(u64::try_from(seconds_elapsed).unwrap_or(0))
+    }
+
     /// Set last update slot
     pub fn update_slot(&mut self, slot: Slot) {
         self.slot = slot;
         self.stale = false;
     }
 
+    /// Set last update slot and unix timestamp, never moving the unix timestamp backwards
+    pub fn update(&mut self, clock: &Clock) {
+        self.update_slot(clock.slot);
+        self.unix_timestamp = self.unix_timestamp.max(clock.unix_timestamp);
+    }
+
     /// Set stale to true
     pub fn mark_stale(&mut self) {
         self.stale = true;
     }
 
-    /// Check if marked stale or last update slot is too long ago
-    pub fn is_stale(&self, slot: Slot) -> Result<bool, ProgramError> {
-        Ok(self.stale || self.slots_elapsed(slot)? >= STALE_AFTER_SLOTS_ELAPSED)
+    /// Check if marked stale or last update slot is `stale_after_slots_elapsed` or more slots ago
+    pub fn is_stale(
+        &self,
+        slot: Slot,
+        stale_after_slots_elapsed: u64,
+    ) -> Result<bool, ProgramError> {
+        Ok(self.stale || self.slots_elapsed(slot)? >= stale_after_slots_elapsed)
     }
 }
 
@@ -1,4 +1,5 @@
 use super::*;
+use crate::state::last_update::STALE_AFTER_SLOTS_ELAPSED;
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use solana_program::{
     msg,
@@ -39,7 +40,9 @@ pub struct LendingMarket {
     pub lock_larix_times_to_time: u64,
     /// 200 means max claim 2 times and must equals or great than 100
     pub max_claim_times: u16,
-
+    /// Number of slots after which reserves and obligations are stale, see `LastUpdate::is_stale`
+    /// 0 to use `STALE_AFTER_SLOTS_ELAPSED`
+    pub stale_after_slots_elapsed: u64,
 }
 
 impl LendingMarket {
@@ -65,6 +68,15 @@ impl LendingMarket {
         self.mine_supply_account = params.mine_supply_account;
         self.mine_lock_program = params.mine_lock_program;
     }
+
+    /// Number of slots after which reserves and obligations of the market are stale
+    pub fn stale_after_slots_elapsed(&self) -> u64 {
+        if self.stale_after_slots_elapsed == 0 {
+            STALE_AFTER_SLOTS_ELAPSED
+        } else {
+            self.stale_after_slots_elapsed
+        }
+    }
 }
 
 /// Initialize a lending market
@@ -122,6 +134,7 @@ impl Pack for LendingMarket {
             mine_lock_program,
             lock_larix_times_to_time,
             max_claim_times,
+            stale_after_slots_elapsed,
             _padding
         ) = mut_array_refs![
             output,
@@ -139,7 +152,8 @@ impl Pack for LendingMarket {
             PUBKEY_BYTES,
             8,
             2,
-            86
+            8,
+            78
         ];
 
         *version = self.version.to_le_bytes();
@@ -156,6 +170,7 @@ impl Pack for LendingMarket {
         mine_lock_program.copy_from_slice(self.mine_lock_program.as_ref());
         *lock_larix_times_to_time = self.lock_larix_times_to_time.to_le_bytes();
         *max_claim_times = self.max_claim_times.to_le_bytes();
+        *stale_after_slots_elapsed = self.stale_after_slots_elapsed.to_le_bytes();
 
     }
 
@@ -178,6 +193,7 @@ impl Pack for LendingMarket {
             mine_lock_program,
             lock_larix_times_to_time,
             max_claim_times,
+            stale_after_slots_elapsed,
             _padding,
         ) = array_refs![
             input,
@@ -195,7 +211,8 @@ impl Pack for LendingMarket {
             PUBKEY_BYTES,
             8,
             2,
-            86
+            8,
+            78
         ];
 
         let version = u8::from_le_bytes(*version);
@@ -219,6 +236,7 @@ impl Pack for LendingMarket {
             mine_lock_program: Pubkey::new_from_array(*mine_lock_program),
             lock_larix_times_to_time: u64::from_le_bytes(*lock_larix_times_to_time),
             max_claim_times: u16::from_le_bytes(*max_claim_times),
+            stale_after_slots_elapsed: u64::from_le_bytes(*stale_after_slots_elapsed),
         })
     }
 }
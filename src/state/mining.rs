@@ -0,0 +1,372 @@
+use super::*;
+use crate::{
+    error::LendingError,
+    math::{Decimal, TryAdd, TryMul, TrySub},
+};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+use std::{
+    convert::{TryFrom},
+};
+
+/// Max number of reserves whose LTokens can be staked in a mining account
+pub const MAX_MINING_RESERVES: usize = 10;
+
+/// Lending market mining state
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mining {
+    /// Version of the struct
+    pub version: u8,
+    /// Owner authority which can deposit and withdraw LTokens
+    pub owner: Pubkey,
+    /// Lending market address
+    pub lending_market: Pubkey,
+    /// Staked LTokens for the mining account, unique by reserve address
+    pub mining_indices: Vec<MiningIndex>,
+    /// Total unclaimed mine of the mining account
+    pub unclaimed_mine: Decimal,
+}
+
+impl Mining {
+    /// Create a new mining account
+    pub fn new(params: InitMiningParams) -> Self {
+        let mut mining = Self::default();
+        Self::init(&mut mining, params);
+        mining
+    }
+
+    /// Initialize a mining account
+    pub fn init(&mut self, params: InitMiningParams) {
+        self.version = PROGRAM_VERSION;
+        self.owner = params.owner;
+        self.lending_market = params.lending_market;
+        self.mining_indices = vec![];
+        self.unclaimed_mine = Decimal::zero();
+    }
+
+    /// Find the position of the mining index of a reserve
+    pub fn find_mining_index(&self, reserve: Pubkey) -> Result<usize, ProgramError> {
+        if self.mining_indices.is_empty() {
+            msg!("Mining has no staked LTokens");
+            return Err(LendingError::MiningReserveEmpty.into());
+        }
+        self.mining_indices
+            .iter()
+            .position(|mining_index| mining_index.reserve == reserve)
+            .ok_or_else(|| {
+                msg!("Mining has no staked LTokens of this reserve");
+                LendingError::InvalidMiningReserve.into()
+            })
+    }
+
+    /// Stake LTokens of a reserve, the mining index must be accrued beforehand
+    pub fn deposit(
+        &mut self,
+        reserve: Pubkey,
+        l_token_mining_index: Decimal,
+        amount: u64,
+    ) -> Result<(), ProgramError> {
+        let position = match self
+            .mining_indices
+            .iter()
+            .position(|mining_index| mining_index.reserve == reserve)
+        {
+            Some(position) => position,
+            None => {
+                if self.mining_indices.len() >= MAX_MINING_RESERVES {
+                    msg!("Mining reserve limit exceeded");
+                    return Err(LendingError::MiningVolumeLimit.into());
+                }
+                self.mining_indices
+                    .push(MiningIndex::new(reserve, l_token_mining_index));
+                self.mining_indices.len() - 1
+            }
+        };
+        self.mining_indices[position].deposit(amount)
+    }
+
+    /// Unstake LTokens of a reserve, the mining index must be accrued beforehand
+    pub fn withdraw(&mut self, reserve: Pubkey, amount: u64) -> Result<(), ProgramError> {
+        let position = self.find_mining_index(reserve)?;
+        let mining_index = &mut self.mining_indices[position];
+        mining_index.withdraw(amount)?;
+        if mining_index.amount == 0 {
+            self.mining_indices.remove(position);
+        }
+        Ok(())
+    }
+
+    /// Accrue unclaimed mine of a reserve up to the reserve's current LToken mining index
+    pub fn accrue(
+        &mut self,
+        reserve: Pubkey,
+        l_token_mining_index: Decimal,
+    ) -> Result<(), ProgramError> {
+        let position = self.find_mining_index(reserve)?;
+        let mine = self.mining_indices[position].accrue(l_token_mining_index)?;
+        self.unclaimed_mine = self.unclaimed_mine.try_add(mine)?;
+        Ok(())
+    }
+
+    /// Check that the mining account belongs to the given owner
+    pub fn check_owner(&self, owner: &Pubkey) -> Result<(), ProgramError> {
+        if &self.owner != owner {
+            msg!("Mining owner does not match the owner provided");
+            return Err(LendingError::InvalidMininigOwner.into());
+        }
+        Ok(())
+    }
+
+    /// Check that the reserves provided are the mining reserves, all, in order
+    pub fn check_reserves(&self, reserves: &[Pubkey]) -> Result<(), ProgramError> {
+        if reserves.len() != self.mining_indices.len()
+            || self
+                .mining_indices
+                .iter()
+                .zip(reserves)
+                .any(|(mining_index, reserve)| &mining_index.reserve != reserve)
+        {
+            msg!("Reserves provided do not match the mining reserves");
+            return Err(LendingError::MiningMiningReserveMismatch.into());
+        }
+        Ok(())
+    }
+
+    /// Check that every reserve occurs once among the mining indices
+    pub fn check_no_repeat_reserve(&self) -> Result<(), ProgramError> {
+        for (position, mining_index) in self.mining_indices.iter().enumerate() {
+            if self.mining_indices[position + 1..]
+                .iter()
+                .any(|other| other.reserve == mining_index.reserve)
+            {
+                msg!("Mining reserve repeat");
+                return Err(LendingError::MiningReserveRepeat.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Initialize a mining account
+pub struct InitMiningParams {
+    /// Owner authority which can deposit and withdraw LTokens
+    pub owner: Pubkey,
+    /// Lending market address
+    pub lending_market: Pubkey,
+}
+
+impl Sealed for Mining {}
+impl IsInitialized for Mining {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+/// Mining state of the LTokens of one reserve
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MiningIndex {
+    /// Reserve the LTokens belong to
+    pub reserve: Pubkey,
+    /// Amount of staked LTokens
+    pub amount: u64,
+    /// LToken mining index of the reserve when last accrued
+    pub index: Decimal,
+}
+
+impl MiningIndex {
+    /// Create new mining index
+    pub fn new(reserve: Pubkey, index: Decimal) -> Self {
+        Self {
+            reserve,
+            amount: 0,
+            index,
+        }
+    }
+
+    /// Increase staked LTokens
+    pub fn deposit(&mut self, amount: u64) -> Result<(), ProgramError> {
+        self.amount = self
+            .amount
+            .checked_add(amount)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Decrease staked LTokens
+    pub fn withdraw(&mut self, amount: u64) -> Result<(), ProgramError> {
+        if amount > self.amount {
+            msg!("Withdraw amount too large for staked LTokens");
+            return Err(LendingError::WithdrawAmountTooLarge.into());
+        }
+        self.amount -= amount;
+        Ok(())
+    }
+
+    /// Move the index forward and return the mine earned since the last accrual
+    pub fn accrue(&mut self, l_token_mining_index: Decimal) -> Result<Decimal, ProgramError> {
+        let mine = l_token_mining_index
+            .try_sub(self.index)?
+            .try_mul(self.amount)?;
+        self.index = l_token_mining_index;
+        Ok(mine)
+    }
+}
+
+const MINING_INDEX_LEN: usize = 56; // 32 + 8 + 16
+const MINING_LEN: usize = 642; // 1 + 32 + 32 + 1 + 16 + (56 * 10)
+impl Pack for Mining {
+    const LEN: usize = MINING_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let output = array_mut_ref![dst, 0, MINING_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            owner,
+            lending_market,
+            mining_indices_len,
+            unclaimed_mine,
+            data_flat,
+        ) = mut_array_refs![
+            output,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            1,
+            16,
+            MINING_INDEX_LEN * MAX_MINING_RESERVES
+        ];
+
+        *version = self.version.to_le_bytes();
+        owner.copy_from_slice(self.owner.as_ref());
+        lending_market.copy_from_slice(self.lending_market.as_ref());
+        *mining_indices_len = u8::try_from(self.mining_indices.len()).unwrap().to_le_bytes();
+        pack_decimal(self.unclaimed_mine, unclaimed_mine);
+
+        let mut offset = 0;
+        for mining_index in &self.mining_indices {
+            let mining_index_flat = array_mut_ref![data_flat, offset, MINING_INDEX_LEN];
+            #[allow(clippy::ptr_offset_with_cast)]
+            let (
+                reserve,
+                amount,
+                index
+            ) = mut_array_refs![mining_index_flat, PUBKEY_BYTES, 8, 16];
+            reserve.copy_from_slice(mining_index.reserve.as_ref());
+            *amount = mining_index.amount.to_le_bytes();
+            pack_decimal(mining_index.index, index);
+            offset += MINING_INDEX_LEN;
+        }
+    }
+
+    /// Unpacks a byte buffer into a [Mining](struct.Mining.html).
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![src, 0, MINING_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            owner,
+            lending_market,
+            mining_indices_len,
+            unclaimed_mine,
+            data_flat,
+        ) = array_refs![
+            input,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            1,
+            16,
+            MINING_INDEX_LEN * MAX_MINING_RESERVES
+        ];
+
+        let version = u8::from_le_bytes(*version);
+        if version > PROGRAM_VERSION {
+            msg!("Mining version does not match lending program version");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mining_indices_len = u8::from_le_bytes(*mining_indices_len) as usize;
+        if mining_indices_len > MAX_MINING_RESERVES {
+            msg!("Mining reserve count exceeds the mining account capacity");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let mut mining_indices = Vec::with_capacity(mining_indices_len + 1);
+
+        let mut offset = 0;
+        for _ in 0..mining_indices_len {
+            let mining_index_flat = array_ref![data_flat, offset, MINING_INDEX_LEN];
+            #[allow(clippy::ptr_offset_with_cast)]
+            let (
+                reserve,
+                amount,
+                index
+            ) = array_refs![mining_index_flat, PUBKEY_BYTES, 8, 16];
+            mining_indices.push(MiningIndex {
+                reserve: Pubkey::new_from_array(*reserve),
+                amount: u64::from_le_bytes(*amount),
+                index: unpack_decimal(index),
+            });
+            offset += MINING_INDEX_LEN;
+        }
+
+        Ok(Self {
+            version,
+            owner: Pubkey::new_from_array(*owner),
+            lending_market: Pubkey::new_from_array(*lending_market),
+            mining_indices,
+            unclaimed_mine: unpack_decimal(unclaimed_mine),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pack_and_unpack_mining() {
+        let reserve = Pubkey::new_unique();
+        let mut mining = Mining::new(InitMiningParams {
+            owner: Pubkey::new_unique(),
+            lending_market: Pubkey::new_unique(),
+        });
+        mining.deposit(reserve, Decimal::from(3u64), 100).unwrap();
+        mining.accrue(reserve, Decimal::from(5u64)).unwrap();
+
+        let mut data = [0u8; Mining::LEN];
+        Mining::pack(mining.clone(), &mut data).unwrap();
+        let unpacked = Mining::unpack(&data).unwrap();
+        assert_eq!(unpacked, mining);
+        assert_eq!(unpacked.unclaimed_mine, Decimal::from(200u64));
+        assert_eq!(unpacked.mining_indices[0].index, Decimal::from(5u64));
+    }
+
+    #[test]
+    fn withdraw_mining() {
+        let reserve = Pubkey::new_unique();
+        let mut mining = Mining::default();
+        assert_eq!(
+            mining.withdraw(reserve, 1),
+            Err(LendingError::MiningReserveEmpty.into())
+        );
+
+        mining.deposit(reserve, Decimal::zero(), 10).unwrap();
+        assert_eq!(
+            mining.withdraw(Pubkey::new_unique(), 1),
+            Err(LendingError::InvalidMiningReserve.into())
+        );
+        assert_eq!(
+            mining.withdraw(reserve, 11),
+            Err(LendingError::WithdrawAmountTooLarge.into())
+        );
+
+        mining.withdraw(reserve, 10).unwrap();
+        assert!(mining.mining_indices.is_empty());
+    }
+}
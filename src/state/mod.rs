@@ -5,6 +5,7 @@ use crate::math::{Decimal};
 pub mod obligation;
 pub mod last_update;
 pub mod lending_market;
+pub mod mining;
 pub mod reserve;
 
 /// Percentage of an obligation that can be repaid during each liquidation call
@@ -23,16 +24,18 @@ pub const UNINITIALIZED_VERSION: u8 = 0;
 
 /// Number of slots per year
 pub const SLOTS_PER_YEAR: u64 = 78840000;
+/// Number of seconds per year
+pub const SECONDS_PER_YEAR: u64 = 31_536_000;
 pub const HOST_FEE_RECEIVER_COUNT: usize = 5;
 // Helpers
-fn pack_decimal(decimal: Decimal, dst: &mut [u8; 16]) {
+pub(crate) fn pack_decimal(decimal: Decimal, dst: &mut [u8; 16]) {
     *dst = decimal
         .to_scaled_val()
         .expect("Decimal cannot be packed")
         .to_le_bytes();
 }
 
-fn unpack_decimal(src: &[u8; 16]) -> Decimal {
+pub(crate) fn unpack_decimal(src: &[u8; 16]) -> Decimal {
     Decimal::from_scaled_val(u128::from_le_bytes(*src))
 }
 
@@ -1,6 +1,7 @@
 use super::*;
 use crate::{
-    math::{Decimal},
+    error::LendingError,
+    math::{Decimal, Rate, TryDiv, TryMul, TrySub},
 };
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use solana_program::{
@@ -11,9 +12,11 @@ use solana_program::{
     pubkey::{Pubkey, PUBKEY_BYTES},
 };
 use std::{
-    convert::{TryFrom},
+    cmp::Ordering,
+    convert::{TryFrom, TryInto},
 };
 use crate::state::last_update::LastUpdate;
+use crate::state::reserve::{PriceSide, Reserve};
 
 
 /// Max number of collateral and liquidity reserve accounts combined for an obligation
@@ -65,6 +68,17 @@ impl Obligation {
         self.deposits = params.deposits;
         self.borrows = params.borrows;
     }
+
+    /// Repay liquidity and remove it from borrows if zeroed out
+    pub fn repay(&mut self, settle_amount: Decimal, liquidity_index: usize) -> Result<(), ProgramError> {
+        let liquidity = &mut self.borrows[liquidity_index];
+        if settle_amount >= liquidity.borrowed_amount_wads {
+            self.borrows.remove(liquidity_index);
+        } else {
+            liquidity.repay(settle_amount)?;
+        }
+        Ok(())
+    }
 }
 
 /// Initialize an obligation
@@ -101,6 +115,18 @@ pub struct ObligationCollateral {
     pub market_value: Decimal,
 }
 
+impl ObligationCollateral {
+    /// Update the market value of the deposit given its amount in reserve liquidity
+    pub fn update_market_value(
+        &mut self,
+        reserve: &Reserve,
+        liquidity_amount: Decimal,
+    ) -> Result<(), ProgramError> {
+        self.market_value = reserve.market_value(liquidity_amount, PriceSide::Collateral)?;
+        Ok(())
+    }
+}
+
 /// Obligation liquidity state
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct ObligationLiquidity {
@@ -116,6 +142,40 @@ pub struct ObligationLiquidity {
     pub market_value: Decimal,
 }
 
+impl ObligationLiquidity {
+    /// Update the market value of the borrow
+    pub fn update_market_value(&mut self, reserve: &Reserve) -> Result<(), ProgramError> {
+        self.market_value = reserve.market_value(self.borrowed_amount_wads, PriceSide::Borrow)?;
+        Ok(())
+    }
+
+    /// Decrease borrowed liquidity
+    pub fn repay(&mut self, settle_amount: Decimal) -> Result<(), ProgramError> {
+        self.borrowed_amount_wads = self.borrowed_amount_wads.try_sub(settle_amount)?;
+        Ok(())
+    }
+
+    /// Accrue interest up to the cumulative borrow rate of the reserve
+    pub fn accrue_interest(&mut self, cumulative_borrow_rate_wads: Decimal) -> Result<(), ProgramError> {
+        match cumulative_borrow_rate_wads.cmp(&self.cumulative_borrow_rate_wads) {
+            Ordering::Less => {
+                msg!("Interest rate cannot be negative");
+                return Err(LendingError::NegativeInterestRate.into());
+            }
+            Ordering::Equal => {}
+            Ordering::Greater => {
+                let borrowed_amount_wads_multiplier: Rate = cumulative_borrow_rate_wads
+                    .try_div(self.cumulative_borrow_rate_wads)?
+                    .try_into()?;
+                self.borrowed_amount_wads = self
+                    .borrowed_amount_wads
+                    .try_mul(borrowed_amount_wads_multiplier)?;
+                self.cumulative_borrow_rate_wads = cumulative_borrow_rate_wads;
+            }
+        }
+        Ok(())
+    }
+}
 
 const OBLIGATION_COLLATERAL_LEN: usize = 72; // 32 + 8 + 16 + 16
 const OBLIGATION_LIQUIDITY_LEN: usize = 96; // 32 + 16 + 16 + 16
@@ -301,6 +361,7 @@ impl Pack for Obligation {
             last_update: LastUpdate {
                 slot: u64::from_le_bytes(*last_update_slot),
                 stale: unpack_bool(last_update_stale)?,
+                unix_timestamp: 0,
             },
             lending_market: Pubkey::new_from_array(*lending_market),
             owner: Pubkey::new_from_array(*owner),
@@ -318,7 +379,7 @@ impl Pack for Obligation {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::math::TryAdd;
+    use crate::math::{TryAdd, WAD};
     use proptest::prelude::*;
 
     const MAX_COMPOUNDED_INTEREST: u64 = 100; // 10,000%
@@ -354,6 +415,71 @@ mod test {
         );
     }
 
+    #[test]
+    fn update_market_value_prices_conservatively() {
+        let mut reserve = Reserve::default();
+        reserve.liquidity.mint_decimals = 6;
+        reserve.liquidity.market_price = Decimal::from(10u64);
+        reserve.liquidity.market_price_confidence = Decimal::one();
+        reserve.config.confidence_multiplier = 2;
+
+        let mut collateral = ObligationCollateral::default();
+        collateral
+            .update_market_value(&reserve, Decimal::from(3_000_000u64))
+            .unwrap();
+        assert_eq!(collateral.market_value, Decimal::from(24u64));
+
+        let mut liquidity = ObligationLiquidity {
+            borrowed_amount_wads: Decimal::from(3_000_000u64),
+            ..ObligationLiquidity::default()
+        };
+        liquidity.update_market_value(&reserve).unwrap();
+        assert_eq!(liquidity.market_value, Decimal::from(36u64));
+
+        reserve.liquidity.market_price_confidence = Decimal::from(6u64);
+        collateral
+            .update_market_value(&reserve, Decimal::from(3_000_000u64))
+            .unwrap();
+        assert_eq!(collateral.market_value, Decimal::zero());
+    }
+
+    #[test]
+    fn pack_and_unpack_obligation() {
+        let obligation = Obligation {
+            version: PROGRAM_VERSION,
+            last_update: LastUpdate {
+                slot: 7,
+                stale: true,
+                unix_timestamp: 1_600_000_000,
+            },
+            lending_market: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            deposits: vec![ObligationCollateral {
+                index: Decimal::one(),
+                deposit_reserve: Pubkey::new_unique(),
+                deposited_amount: 5,
+                market_value: Decimal::from(50u64),
+            }],
+            borrows: vec![ObligationLiquidity {
+                index: Decimal::one(),
+                borrow_reserve: Pubkey::new_unique(),
+                cumulative_borrow_rate_wads: Decimal::one(),
+                borrowed_amount_wads: Decimal::from(2u64),
+                market_value: Decimal::from(20u64),
+            }],
+            deposited_value: Decimal::from(50u64),
+            borrowed_value: Decimal::from(20u64),
+            ..Obligation::default()
+        };
+        let mut data = [0; OBLIGATION_LEN];
+        Obligation::pack(obligation.clone(), &mut data).unwrap();
+        let unpacked = Obligation::unpack(&data).unwrap();
+        assert_eq!(unpacked, obligation);
+        // obligations do not store the unix timestamp of their last update
+        assert_eq!(unpacked.last_update.unix_timestamp, 0);
+        assert!(unpacked.last_update.stale);
+    }
+
     // Creates rates (r1, r2) where 0 < r1 <= r2 <= 100*r1
     prop_compose! {
         fn cumulative_rates()(rate in 1..=u128::MAX)(
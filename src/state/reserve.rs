@@ -1,10 +1,12 @@
 use super::*;
 use crate::{
-    math::{Decimal, Rate},
+    error::LendingError,
+    math::{Decimal, Rate, Rounding, TryAdd, TryDiv, TryMul, TrySub},
+    util::price_source::PriceSource,
 };
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use solana_program::{
-    clock::Slot,
+    clock::{Clock, Slot},
     msg,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
@@ -13,7 +15,7 @@ use solana_program::{
 use std::{
     convert::{TryFrom},
 };
-use crate::state::last_update::LastUpdate;
+use crate::state::{last_update::LastUpdate, lending_market::LendingMarket};
 
 pub mod init_reserve_accounts_index{
     ///   0. `[writable]` Reserve account - uninitialized.
@@ -79,6 +81,213 @@ pub struct Reserve {
 
 }
 
+/// Side of an obligation a reserve price is used for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PriceSide {
+    /// Deposits are valued at the low end of the confidence interval
+    Collateral,
+    /// Borrows are valued at the high end of the confidence interval
+    Borrow,
+}
+
+impl Reserve {
+    /// Record a new market price, rejecting it if its confidence interval is too wide
+    pub fn set_market_price(
+        &mut self,
+        market_price: Decimal,
+        confidence: Decimal,
+    ) -> Result<(), ProgramError> {
+        if self.config.max_confidence_ratio_wad != 0 {
+            let max_confidence = market_price
+                .try_mul(Rate::from_scaled_val(self.config.max_confidence_ratio_wad))?;
+            if confidence > max_confidence {
+                msg!("Oracle price confidence interval is too wide");
+                return Err(LendingError::OracleConfidenceTooWide.into());
+            }
+        }
+        self.liquidity.market_price = market_price;
+        self.liquidity.market_price_confidence = confidence;
+        Ok(())
+    }
+
+    /// Refresh the reserve: accrue interest, then update the market price from a price source
+    ///
+    /// Accruing interest updates the last update slot and clears the stale flag, so it comes
+    /// first and a price out of the price move bound leaves the reserve marked stale.
+    pub fn refresh(
+        &mut self,
+        current_borrow_rate: Rate,
+        source: &impl PriceSource,
+        clock: &Clock,
+    ) -> Result<(), ProgramError> {
+        self.accrue_interest(current_borrow_rate, clock)?;
+        self.refresh_market_price(source, clock)
+    }
+
+    /// Update the market price from a price source, see `refresh` for the whole reserve
+    ///
+    /// A price moving more than `max_price_move_wad` away from the price the current window
+    /// started at is rejected, or leaves the last price in place and marks the reserve stale.
+    /// Once the window has elapsed, such a price is clamped to `max_price_move_wad` around the
+    /// last accepted price, so the market price follows the oracle one window at a time.
+    pub fn refresh_market_price(
+        &mut self,
+        source: &impl PriceSource,
+        clock: &Clock,
+    ) -> Result<(), ProgramError> {
+        let (market_price, confidence) = source.price_and_confidence(clock)?;
+        self.update_market_price(market_price, confidence, clock)
+    }
+
+    /// Record a market price read from an oracle at the clock slot, subject to the price move
+    /// bound of `refresh_market_price`
+    pub fn update_market_price(
+        &mut self,
+        market_price: Decimal,
+        confidence: Decimal,
+        clock: &Clock,
+    ) -> Result<(), ProgramError> {
+        let market_price = match self.bound_price_move(market_price, clock.slot)? {
+            Some(market_price) => market_price,
+            None => {
+                self.last_update.mark_stale();
+                return Ok(());
+            }
+        };
+        self.set_market_price(market_price, confidence)?;
+        let move_anchor = self.liquidity.market_price_anchor == Decimal::zero()
+            || self.price_move_window_elapsed(clock.slot);
+        let liquidity = &mut self.liquidity;
+        liquidity.market_price_slot = clock.slot;
+        if move_anchor {
+            liquidity.market_price_anchor = market_price;
+            liquidity.market_price_anchor_slot = clock.slot;
+        }
+        Ok(())
+    }
+
+    fn price_move_window_elapsed(&self, slot: Slot) -> bool {
+        slot.saturating_sub(self.liquidity.market_price_anchor_slot)
+            >= self.config.price_move_window_slots
+    }
+
+    /// Bound a new market price by the price move bound, measured from the price the current
+    /// window started at, or clamped around the last accepted price once the window has
+    /// elapsed. Returns `None` if the reserve should be marked stale instead.
+    fn bound_price_move(
+        &self,
+        market_price: Decimal,
+        slot: Slot,
+    ) -> Result<Option<Decimal>, ProgramError> {
+        let liquidity = &self.liquidity;
+        if self.config.max_price_move_wad == 0 || liquidity.market_price_anchor == Decimal::zero()
+        {
+            return Ok(Some(market_price));
+        }
+
+        let window_elapsed = self.price_move_window_elapsed(slot);
+        let reference = if window_elapsed {
+            liquidity.market_price
+        } else {
+            liquidity.market_price_anchor
+        };
+        let max_price_move =
+            reference.try_mul(Rate::from_scaled_val(self.config.max_price_move_wad))?;
+        let lower_bound = reference
+            .try_sub(max_price_move)
+            .unwrap_or_else(|_| Decimal::zero());
+        let upper_bound = reference.try_add(max_price_move)?;
+        if market_price >= lower_bound && market_price <= upper_bound {
+            return Ok(Some(market_price));
+        }
+        if window_elapsed {
+            msg!("Market price clamped to the reserve price move bound");
+            return Ok(Some(market_price.max(lower_bound).min(upper_bound)));
+        }
+        msg!("Market price moved out of the reserve price move bound");
+        if self.config.reject_price_move {
+            return Err(LendingError::PriceMoveTooLarge.into());
+        }
+        Ok(None)
+    }
+
+    /// Number of slots after which the reserve is stale
+    pub fn stale_after_slots_elapsed(&self, lending_market: &LendingMarket) -> u64 {
+        if self.config.stale_after_slots_elapsed == 0 {
+            lending_market.stale_after_slots_elapsed()
+        } else {
+            self.config.stale_after_slots_elapsed
+        }
+    }
+
+    /// Check if the reserve is marked stale or was last refreshed too long ago
+    pub fn is_stale(&self, slot: Slot, lending_market: &LendingMarket) -> Result<bool, ProgramError> {
+        self.last_update
+            .is_stale(slot, self.stale_after_slots_elapsed(lending_market))
+    }
+
+    /// Compound interest at the current borrow rate since the last update, then update it
+    ///
+    /// Interest accrues per slot assuming `SLOTS_PER_YEAR`, or per second when
+    /// `use_timestamp_accrual` is set and a timestamp was recorded by a previous update.
+    pub fn accrue_interest(
+        &mut self,
+        current_borrow_rate: Rate,
+        clock: &Clock,
+    ) -> Result<(), ProgramError> {
+        let seconds_elapsed = if self.config.use_timestamp_accrual {
+            self.last_update.seconds_elapsed(clock.unix_timestamp)
+        } else {
+            None
+        };
+        let (periods_elapsed, periods_per_year) = match seconds_elapsed {
+            Some(seconds_elapsed) => (seconds_elapsed, SECONDS_PER_YEAR),
+            None => (self.last_update.slots_elapsed(clock.slot)?, SLOTS_PER_YEAR),
+        };
+        if periods_elapsed > 0 {
+            self.liquidity
+                .compound_interest(current_borrow_rate, periods_elapsed, periods_per_year)?;
+        }
+        self.last_update.update(clock);
+        Ok(())
+    }
+
+    /// Market price moved by `confidence_multiplier` confidence intervals against the borrower
+    pub fn conservative_price(&self, side: PriceSide) -> Result<Decimal, ProgramError> {
+        let adjustment = self
+            .liquidity
+            .market_price_confidence
+            .try_mul(self.config.confidence_multiplier as u64)?;
+        match side {
+            PriceSide::Collateral => Ok(self
+                .liquidity
+                .market_price
+                .try_sub(adjustment)
+                .unwrap_or_else(|_| Decimal::zero())),
+            PriceSide::Borrow => self.liquidity.market_price.try_add(adjustment),
+        }
+    }
+
+    /// Market value in quote currency of an amount of liquidity, rounded down for collateral
+    /// and up for borrows
+    pub fn market_value(
+        &self,
+        liquidity_amount: Decimal,
+        side: PriceSide,
+    ) -> Result<Decimal, ProgramError> {
+        let decimals = 10u64
+            .checked_pow(self.liquidity.mint_decimals as u32)
+            .ok_or(LendingError::MathOverflow)?;
+        let rounding = match side {
+            PriceSide::Collateral => Rounding::Floor,
+            PriceSide::Borrow => Rounding::Ceiling,
+        };
+        liquidity_amount
+            .try_mul_rounded(self.conservative_price(side)?, rounding)?
+            .try_div_rounded(decimals, rounding)
+    }
+}
+
 
 /// Calculate borrow result
 #[derive(Debug)]
@@ -144,7 +353,38 @@ pub struct ReserveLiquidity {
     /// Reserve liquidity market price in quote currency
     pub market_price: Decimal,
     /// unclaimed fee by reserve owner
-    pub owner_unclaimed: Decimal
+    pub owner_unclaimed: Decimal,
+    /// Confidence interval of the market price in quote currency
+    pub market_price_confidence: Decimal,
+    /// Market price the price moves of the current window are measured from
+    pub market_price_anchor: Decimal,
+    /// Slot when the current price move window started
+    pub market_price_anchor_slot: Slot,
+    /// Slot when the market price was last read from an oracle
+    pub market_price_slot: Slot,
+}
+
+impl ReserveLiquidity {
+    /// Compound the borrow rate per year over periods elapsed, then add the interest to the
+    /// borrowed amount and cumulative borrow rate
+    pub fn compound_interest(
+        &mut self,
+        current_borrow_rate: Rate,
+        periods_elapsed: u64,
+        periods_per_year: u64,
+    ) -> Result<(), ProgramError> {
+        let period_rate = current_borrow_rate.try_div(periods_per_year)?;
+        let compounded_interest_rate = Rate::one()
+            .try_add(period_rate)?
+            .try_pow_approx(periods_elapsed)?;
+        self.cumulative_borrow_rate_wads = self
+            .cumulative_borrow_rate_wads
+            .try_mul(compounded_interest_rate)?;
+        self.borrowed_amount_wads = self
+            .borrowed_amount_wads
+            .try_mul(compounded_interest_rate)?;
+        Ok(())
+    }
 }
 
 
@@ -277,6 +517,24 @@ pub struct ReserveConfig {
     pub liquidation_paused:bool,
     /// Deposit limit
     pub deposit_limit:u64,
+    /// Number of confidence intervals deposits and borrows are priced away from the market price
+    /// 0 if conservative pricing is disabled
+    pub confidence_multiplier: u8,
+    /// Max ratio of the confidence interval to the market price, expressed as a Wad
+    /// 0 if the confidence interval is not checked
+    pub max_confidence_ratio_wad: u64,
+    /// Max relative market price move within a price move window, expressed as a Wad
+    /// 0 if price moves are not bounded
+    pub max_price_move_wad: u64,
+    /// Length of a price move window in slots
+    pub price_move_window_slots: u64,
+    /// Reject refreshes moving the price out of bounds, instead of marking the reserve stale
+    pub reject_price_move: bool,
+    /// Number of slots after which the reserve is stale, see `LastUpdate::is_stale`
+    /// 0 to use the lending market value
+    pub stale_after_slots_elapsed: u64,
+    /// Accrue interest over elapsed `Clock::unix_timestamp` seconds instead of elapsed slots
+    pub use_timestamp_accrual: bool,
 }
 
 /// Additional fee information on a reserve
@@ -380,6 +638,18 @@ impl Pack for Reserve {
             reentry_lock,
             deposit_limit,
             is_lp,
+            liquidity_market_price_confidence,
+            config_confidence_multiplier,
+            config_max_confidence_ratio_wad,
+            liquidity_market_price_anchor,
+            liquidity_market_price_anchor_slot,
+            config_max_price_move_wad,
+            config_price_move_window_slots,
+            config_reject_price_move,
+            config_stale_after_slots_elapsed,
+            last_update_unix_timestamp,
+            config_use_timestamp_accrual,
+            liquidity_market_price_slot,
             _padding,
         ) = mut_array_refs![
             output,
@@ -429,7 +699,19 @@ impl Pack for Reserve {
             1,
             8,
             1,
-            239
+            16,
+            1,
+            8,
+            16,
+            8,
+            8,
+            8,
+            1,
+            8,
+            8,
+            1,
+            8,
+            148
         ];
 
         // reserve
@@ -496,6 +778,21 @@ impl Pack for Reserve {
         *supply_rate = self.bonus.supply_rate.to_le_bytes();
         pack_decimal(self.liquidity.owner_unclaimed, owner_unclaimed);
         pack_bool(self.reentry_lock, reentry_lock);
+        pack_decimal(
+            self.liquidity.market_price_confidence,
+            liquidity_market_price_confidence,
+        );
+        *config_confidence_multiplier = self.config.confidence_multiplier.to_le_bytes();
+        *config_max_confidence_ratio_wad = self.config.max_confidence_ratio_wad.to_le_bytes();
+        pack_decimal(self.liquidity.market_price_anchor, liquidity_market_price_anchor);
+        *liquidity_market_price_anchor_slot = self.liquidity.market_price_anchor_slot.to_le_bytes();
+        *config_max_price_move_wad = self.config.max_price_move_wad.to_le_bytes();
+        *config_price_move_window_slots = self.config.price_move_window_slots.to_le_bytes();
+        pack_bool(self.config.reject_price_move, config_reject_price_move);
+        *config_stale_after_slots_elapsed = self.config.stale_after_slots_elapsed.to_le_bytes();
+        *last_update_unix_timestamp = self.last_update.unix_timestamp.to_le_bytes();
+        pack_bool(self.config.use_timestamp_accrual, config_use_timestamp_accrual);
+        *liquidity_market_price_slot = self.liquidity.market_price_slot.to_le_bytes();
     }
 
     /// Unpacks a byte buffer into a [ReserveInfo](struct.ReserveInfo.html).
@@ -546,6 +843,18 @@ impl Pack for Reserve {
             reentry_lock,
             deposit_limit,
             is_lp,
+            liquidity_market_price_confidence,
+            config_confidence_multiplier,
+            config_max_confidence_ratio_wad,
+            liquidity_market_price_anchor,
+            liquidity_market_price_anchor_slot,
+            config_max_price_move_wad,
+            config_price_move_window_slots,
+            config_reject_price_move,
+            config_stale_after_slots_elapsed,
+            last_update_unix_timestamp,
+            config_use_timestamp_accrual,
+            liquidity_market_price_slot,
             _padding,
         ) = array_refs![
             input,
@@ -592,7 +901,19 @@ impl Pack for Reserve {
             1,
             8,
             1,
-            239
+            16,
+            1,
+            8,
+            16,
+            8,
+            8,
+            8,
+            1,
+            8,
+            8,
+            1,
+            8,
+            148
         ];
 
         let version = u8::from_le_bytes(*version);
@@ -612,6 +933,7 @@ impl Pack for Reserve {
             last_update: LastUpdate {
                 slot: u64::from_le_bytes(*last_update_slot),
                 stale: unpack_bool(last_update_stale)?,
+                unix_timestamp: i64::from_le_bytes(*last_update_unix_timestamp),
             },
             lending_market: Pubkey::new_from_array(*lending_market),
             liquidity: ReserveLiquidity {
@@ -628,6 +950,10 @@ impl Pack for Reserve {
                 cumulative_borrow_rate_wads: unpack_decimal(liquidity_cumulative_borrow_rate_wads),
                 market_price: unpack_decimal(liquidity_market_price),
                 owner_unclaimed: unpack_decimal(owner_unclaimed),
+                market_price_confidence: unpack_decimal(liquidity_market_price_confidence),
+                market_price_anchor: unpack_decimal(liquidity_market_price_anchor),
+                market_price_anchor_slot: u64::from_le_bytes(*liquidity_market_price_anchor_slot),
+                market_price_slot: u64::from_le_bytes(*liquidity_market_price_slot),
             },
             collateral: ReserveCollateral {
                 mint_pubkey: Pubkey::new_from_array(*collateral_mint_pubkey),
@@ -653,6 +979,13 @@ impl Pack for Reserve {
                 borrow_paused:unpack_bool(borrow_paused)?,
                 liquidation_paused:unpack_bool(liquidation_paused)?,
                 deposit_limit:u64::from_le_bytes(*deposit_limit),
+                confidence_multiplier: u8::from_le_bytes(*config_confidence_multiplier),
+                max_confidence_ratio_wad: u64::from_le_bytes(*config_max_confidence_ratio_wad),
+                max_price_move_wad: u64::from_le_bytes(*config_max_price_move_wad),
+                price_move_window_slots: u64::from_le_bytes(*config_price_move_window_slots),
+                reject_price_move: unpack_bool(config_reject_price_move)?,
+                stale_after_slots_elapsed: u64::from_le_bytes(*config_stale_after_slots_elapsed),
+                use_timestamp_accrual: unpack_bool(config_use_timestamp_accrual)?,
             },
             bonus: Bonus{
                 un_coll_supply_account: Pubkey::new_from_array(*un_coll_supply_account),
@@ -665,3 +998,220 @@ impl Pack for Reserve {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{math::WAD, util::price_source::MockPriceSource};
+
+    #[test]
+    fn pack_and_unpack_reserve() {
+        let mut reserve = Reserve {
+            version: PROGRAM_VERSION,
+            ..Reserve::default()
+        };
+        reserve.config.confidence_multiplier = 2;
+        reserve.config.max_confidence_ratio_wad = WAD / 100;
+        reserve.config.max_price_move_wad = WAD / 10;
+        reserve.config.price_move_window_slots = 25;
+        reserve.config.reject_price_move = true;
+        reserve
+            .refresh_market_price(&MockPriceSource::new(Decimal::from(100u64)), &Clock::default())
+            .unwrap();
+
+        let mut data = [0u8; Reserve::LEN];
+        Reserve::pack(reserve.clone(), &mut data).unwrap();
+        assert_eq!(Reserve::unpack(&data).unwrap(), reserve);
+    }
+
+    #[test]
+    fn conservative_price() {
+        let mut reserve = Reserve::default();
+        reserve.liquidity.mint_decimals = 2;
+        reserve.config.confidence_multiplier = 2;
+        reserve.config.max_confidence_ratio_wad = WAD / 100;
+
+        assert_eq!(
+            reserve.set_market_price(Decimal::from(100u64), Decimal::from(2u64)),
+            Err(LendingError::OracleConfidenceTooWide.into())
+        );
+        reserve
+            .set_market_price(Decimal::from(100u64), Decimal::one())
+            .unwrap();
+
+        assert_eq!(
+            reserve.conservative_price(PriceSide::Collateral),
+            Ok(Decimal::from(98u64))
+        );
+        assert_eq!(
+            reserve.conservative_price(PriceSide::Borrow),
+            Ok(Decimal::from(102u64))
+        );
+        assert_eq!(
+            reserve.market_value(Decimal::from(300u64), PriceSide::Borrow),
+            Ok(Decimal::from(306u64))
+        );
+    }
+
+    #[test]
+    fn refresh_market_price_bounds_price_moves() {
+        let mut reserve = Reserve::default();
+        reserve.config.max_price_move_wad = WAD / 10;
+        reserve.config.price_move_window_slots = 10;
+        let refresh = |reserve: &mut Reserve, price: u64, slot: Slot| {
+            reserve.last_update.update_slot(slot);
+            let clock = Clock {
+                slot,
+                ..Clock::default()
+            };
+            reserve.refresh_market_price(&MockPriceSource::new(Decimal::from(price)), &clock)
+        };
+
+        refresh(&mut reserve, 100, 1).unwrap();
+        refresh(&mut reserve, 109, 2).unwrap();
+        assert_eq!(reserve.liquidity.market_price, Decimal::from(109u64));
+        assert!(!reserve.last_update.stale);
+
+        refresh(&mut reserve, 111, 3).unwrap();
+        assert_eq!(reserve.liquidity.market_price, Decimal::from(109u64));
+        assert!(reserve.last_update.stale);
+
+        reserve.config.reject_price_move = true;
+        assert_eq!(
+            refresh(&mut reserve, 80, 4),
+            Err(LendingError::PriceMoveTooLarge.into())
+        );
+
+        // once the window has elapsed, the price is clamped around the last accepted price
+        // and walks toward the oracle price one window at a time
+        let clamped = Decimal::from(1_199u64).try_div(10u64).unwrap();
+        refresh(&mut reserve, 130, 11).unwrap();
+        assert_eq!(reserve.liquidity.market_price, clamped);
+        assert_eq!(reserve.liquidity.market_price_anchor, clamped);
+        assert_eq!(reserve.liquidity.market_price_anchor_slot, 11);
+        assert!(!reserve.last_update.stale);
+
+        refresh(&mut reserve, 130, 12).unwrap();
+        assert_eq!(reserve.liquidity.market_price, Decimal::from(130u64));
+        assert_eq!(reserve.liquidity.market_price_anchor, clamped);
+
+        // a crash is followed down to the oracle price
+        let mut slot = 12;
+        let mut windows = 0;
+        while reserve.liquidity.market_price != Decimal::from(50u64) {
+            slot += 10;
+            windows += 1;
+            refresh(&mut reserve, 50, slot).unwrap();
+            assert!(!reserve.last_update.stale);
+        }
+        assert_eq!(windows, 10);
+    }
+
+    #[test]
+    fn refresh_keeps_out_of_bound_price_stale() {
+        let mut reserve = Reserve::default();
+        reserve.config.max_price_move_wad = WAD / 10;
+        reserve.config.price_move_window_slots = 10;
+        let clock = |slot| Clock {
+            slot,
+            ..Clock::default()
+        };
+        let source = |price: u64| MockPriceSource::new(Decimal::from(price));
+
+        reserve.refresh(Rate::zero(), &source(100), &clock(1)).unwrap();
+        assert!(!reserve.last_update.stale);
+
+        reserve.refresh(Rate::zero(), &source(120), &clock(2)).unwrap();
+        assert_eq!(reserve.liquidity.market_price, Decimal::from(100u64));
+        assert_eq!(reserve.last_update.slot, 2);
+        assert!(reserve.last_update.stale);
+
+        reserve.refresh(Rate::zero(), &source(105), &clock(3)).unwrap();
+        assert_eq!(reserve.liquidity.market_price, Decimal::from(105u64));
+        assert!(!reserve.last_update.stale);
+    }
+
+    #[test]
+    fn stale_after_slots_elapsed() {
+        let mut lending_market = LendingMarket::default();
+        let mut reserve = Reserve::default();
+        reserve.last_update.update_slot(10);
+        assert_eq!(reserve.is_stale(11, &lending_market), Ok(true));
+
+        lending_market.stale_after_slots_elapsed = 3;
+        assert_eq!(reserve.is_stale(12, &lending_market), Ok(false));
+        assert_eq!(reserve.is_stale(13, &lending_market), Ok(true));
+
+        reserve.config.stale_after_slots_elapsed = 5;
+        assert_eq!(reserve.is_stale(14, &lending_market), Ok(false));
+        reserve.last_update.mark_stale();
+        assert_eq!(reserve.is_stale(10, &lending_market), Ok(true));
+    }
+
+    #[test]
+    fn accrue_interest_by_slots_or_seconds() {
+        let mut reserve = Reserve::default();
+        reserve.liquidity.borrowed_amount_wads = Decimal::from(1_000u64);
+        reserve.liquidity.cumulative_borrow_rate_wads = Decimal::one();
+        let clock = |slot, unix_timestamp| Clock {
+            slot,
+            unix_timestamp,
+            ..Clock::default()
+        };
+
+        reserve
+            .accrue_interest(Rate::from_percent(10), &clock(SLOTS_PER_YEAR, 0))
+            .unwrap();
+        let by_slots = reserve.liquidity.borrowed_amount_wads;
+        assert!(by_slots > Decimal::from(1_105u64) && by_slots < Decimal::from(1_106u64));
+
+        let mut reserve = Reserve::default();
+        reserve.liquidity.borrowed_amount_wads = Decimal::from(1_000u64);
+        reserve.liquidity.cumulative_borrow_rate_wads = Decimal::one();
+        reserve.config.use_timestamp_accrual = true;
+        reserve
+            .accrue_interest(Rate::from_percent(10), &clock(0, 1_600_000_000))
+            .unwrap();
+        assert_eq!(reserve.liquidity.borrowed_amount_wads, Decimal::from(1_000u64));
+
+        // Slots running slower than 400ms do not change the interest earned over a year
+        reserve
+            .accrue_interest(
+                Rate::from_percent(10),
+                &clock(SLOTS_PER_YEAR / 2, 1_600_000_000 + SECONDS_PER_YEAR as i64),
+            )
+            .unwrap();
+        let by_seconds = reserve.liquidity.borrowed_amount_wads;
+        assert!(by_seconds > Decimal::from(1_105u64) && by_seconds < Decimal::from(1_106u64));
+        assert_eq!(reserve.last_update.unix_timestamp, 1_600_000_000 + SECONDS_PER_YEAR as i64);
+
+        // A clock behind the recorded timestamp accrues nothing and keeps the timestamp
+        reserve
+            .accrue_interest(
+                Rate::from_percent(10),
+                &clock(SLOTS_PER_YEAR, 1_600_000_000),
+            )
+            .unwrap();
+        assert_eq!(reserve.liquidity.borrowed_amount_wads, by_seconds);
+        assert_eq!(reserve.last_update.unix_timestamp, 1_600_000_000 + SECONDS_PER_YEAR as i64);
+    }
+
+    #[test]
+    fn timestamp_accrual_falls_back_to_slots() {
+        let mut reserve = Reserve::default();
+        reserve.liquidity.borrowed_amount_wads = Decimal::from(1_000u64);
+        reserve.liquidity.cumulative_borrow_rate_wads = Decimal::one();
+        reserve.config.use_timestamp_accrual = true;
+        let clock = Clock {
+            slot: SLOTS_PER_YEAR,
+            unix_timestamp: 1_600_000_000,
+            ..Clock::default()
+        };
+
+        // no timestamp recorded yet, e.g. a reserve switched to timestamp accrual
+        reserve.accrue_interest(Rate::from_percent(10), &clock).unwrap();
+        let by_slots = reserve.liquidity.borrowed_amount_wads;
+        assert!(by_slots > Decimal::from(1_105u64) && by_slots < Decimal::from(1_106u64));
+        assert_eq!(reserve.last_update.unix_timestamp, 1_600_000_000);
+    }
+}
@@ -0,0 +1,268 @@
+//! Accounts of the bridge program backing reserves whose liquidity is an LP token.
+//!
+//! These layouts have not been checked against mainnet accounts of the bridge program. Until
+//! they are, accounts are only loaded when their size matches the layout exactly, so an
+//! account with another layout fails to load instead of producing a price.
+
+use crate::{
+    error::LendingError,
+    math::Decimal,
+    state::{pack_decimal, unpack_decimal},
+};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    clock::{Clock, Slot},
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Number of slots after which bridge state is too old to price an LP token
+pub const BRIDGE_STALE_AFTER_SLOTS_ELAPSED: u64 = 5;
+
+/// Bridge pool state kept by the bridge program, `ReserveLiquidity::params_1` of LP reserves
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BridgePool {
+    /// Version of the struct, 0 if uninitialized
+    pub version: u8,
+    /// AMM the LP token belongs to
+    pub amm_id: Pubkey,
+    /// LP token mint
+    pub lp_mint: Pubkey,
+    /// AMM coin token supply account
+    pub amm_coin_mint_supply: Pubkey,
+    /// AMM pc token supply account
+    pub amm_pc_mint_supply: Pubkey,
+    /// Price account of the AMM coin token
+    pub coin_mint_price: Pubkey,
+    /// Price account of the AMM pc token
+    pub pc_mint_price: Pubkey,
+    /// Slot when the bridge pool was last updated
+    pub last_update_slot: Slot,
+}
+
+impl Sealed for BridgePool {}
+impl IsInitialized for BridgePool {
+    fn is_initialized(&self) -> bool {
+        self.version != 0
+    }
+}
+
+const BRIDGE_POOL_LEN: usize = 201; // 1 + 32 + 32 + 32 + 32 + 32 + 32 + 8
+impl Pack for BridgePool {
+    const LEN: usize = BRIDGE_POOL_LEN;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, BRIDGE_POOL_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            amm_id,
+            lp_mint,
+            amm_coin_mint_supply,
+            amm_pc_mint_supply,
+            coin_mint_price,
+            pc_mint_price,
+            last_update_slot,
+        ) = mut_array_refs![
+            output,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            8
+        ];
+
+        *version = self.version.to_le_bytes();
+        amm_id.copy_from_slice(self.amm_id.as_ref());
+        lp_mint.copy_from_slice(self.lp_mint.as_ref());
+        amm_coin_mint_supply.copy_from_slice(self.amm_coin_mint_supply.as_ref());
+        amm_pc_mint_supply.copy_from_slice(self.amm_pc_mint_supply.as_ref());
+        coin_mint_price.copy_from_slice(self.coin_mint_price.as_ref());
+        pc_mint_price.copy_from_slice(self.pc_mint_price.as_ref());
+        *last_update_slot = self.last_update_slot.to_le_bytes();
+    }
+
+    /// Unpacks a byte buffer into a [BridgePool](struct.BridgePool.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, BRIDGE_POOL_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            amm_id,
+            lp_mint,
+            amm_coin_mint_supply,
+            amm_pc_mint_supply,
+            coin_mint_price,
+            pc_mint_price,
+            last_update_slot,
+        ) = array_refs![
+            input,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            8
+        ];
+
+        Ok(Self {
+            version: u8::from_le_bytes(*version),
+            amm_id: Pubkey::new_from_array(*amm_id),
+            lp_mint: Pubkey::new_from_array(*lp_mint),
+            amm_coin_mint_supply: Pubkey::new_from_array(*amm_coin_mint_supply),
+            amm_pc_mint_supply: Pubkey::new_from_array(*amm_pc_mint_supply),
+            coin_mint_price: Pubkey::new_from_array(*coin_mint_price),
+            pc_mint_price: Pubkey::new_from_array(*pc_mint_price),
+            last_update_slot: u64::from_le_bytes(*last_update_slot),
+        })
+    }
+}
+
+/// Load a bridge pool account, checking its size and initialization
+pub fn load_bridge_pool(data: &[u8]) -> Result<BridgePool, ProgramError> {
+    if data.len() != BridgePool::LEN {
+        msg!("Bridge pool account data size does not match the expected layout");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    let bridge_pool = BridgePool::unpack_from_slice(data)?;
+    if !bridge_pool.is_initialized() {
+        msg!("Bridge pool account is not initialized");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    Ok(bridge_pool)
+}
+
+/// Check that bridge state updated at a slot is recent enough to price an LP token
+pub fn check_bridge_stale(last_update_slot: Slot, clock: &Clock) -> Result<(), ProgramError> {
+    let slots_elapsed = clock.slot.checked_sub(last_update_slot).ok_or_else(|| {
+        msg!("Bridge update slot is ahead of the current slot");
+        LendingError::InvalidOracleConfig
+    })?;
+    if slots_elapsed >= BRIDGE_STALE_AFTER_SLOTS_ELAPSED {
+        msg!("Bridge needs to be refreshed");
+        return Err(LendingError::BridgeStale.into());
+    }
+    Ok(())
+}
+
+/// LP token price state kept by the bridge program, `ReserveLiquidity::params_2` of LP reserves
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LpPrice {
+    /// Version of the struct, 0 if uninitialized
+    pub version: u8,
+    /// Bridge pool the price belongs to, `ReserveLiquidity::params_1` of LP reserves
+    pub bridge_pool: Pubkey,
+    /// Price of one LP token in quote currency
+    pub price: Decimal,
+    /// Slot when the price was last updated
+    pub last_update_slot: Slot,
+}
+
+impl Sealed for LpPrice {}
+impl IsInitialized for LpPrice {
+    fn is_initialized(&self) -> bool {
+        self.version != 0
+    }
+}
+
+const LP_PRICE_LEN: usize = 57; // 1 + 32 + 16 + 8
+impl Pack for LpPrice {
+    const LEN: usize = LP_PRICE_LEN;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, LP_PRICE_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            bridge_pool,
+            price,
+            last_update_slot,
+        ) = mut_array_refs![output, 1, PUBKEY_BYTES, 16, 8];
+
+        *version = self.version.to_le_bytes();
+        bridge_pool.copy_from_slice(self.bridge_pool.as_ref());
+        pack_decimal(self.price, price);
+        *last_update_slot = self.last_update_slot.to_le_bytes();
+    }
+
+    /// Unpacks a byte buffer into a [LpPrice](struct.LpPrice.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, LP_PRICE_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            bridge_pool,
+            price,
+            last_update_slot,
+        ) = array_refs![input, 1, PUBKEY_BYTES, 16, 8];
+
+        Ok(Self {
+            version: u8::from_le_bytes(*version),
+            bridge_pool: Pubkey::new_from_array(*bridge_pool),
+            price: unpack_decimal(price),
+            last_update_slot: u64::from_le_bytes(*last_update_slot),
+        })
+    }
+}
+
+/// Load an LP price account, checking its size and initialization
+pub fn load_lp_price(data: &[u8]) -> Result<LpPrice, ProgramError> {
+    if data.len() != LpPrice::LEN {
+        msg!("LP price account data size does not match the expected layout");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    let lp_price = LpPrice::unpack_from_slice(data)?;
+    if !lp_price.is_initialized() {
+        msg!("LP price account is not initialized");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    Ok(lp_price)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn load_rejects_unexpected_sizes() {
+        for len in [BRIDGE_POOL_LEN - 1, BRIDGE_POOL_LEN + 1] {
+            assert_eq!(
+                load_bridge_pool(&vec![1u8; len]),
+                Err(LendingError::InvalidOracleConfig.into())
+            );
+        }
+        assert!(load_bridge_pool(&[1u8; BRIDGE_POOL_LEN]).is_ok());
+        for len in [LP_PRICE_LEN - 1, LP_PRICE_LEN + 1] {
+            assert_eq!(
+                load_lp_price(&vec![1u8; len]),
+                Err(LendingError::InvalidOracleConfig.into())
+            );
+        }
+        assert!(load_lp_price(&[1u8; LP_PRICE_LEN]).is_ok());
+    }
+
+    #[test]
+    fn check_bridge_stale_rejects_old_and_future_slots() {
+        let clock = Clock {
+            slot: 100,
+            ..Clock::default()
+        };
+        assert_eq!(check_bridge_stale(100, &clock), Ok(()));
+        assert_eq!(check_bridge_stale(100 - BRIDGE_STALE_AFTER_SLOTS_ELAPSED + 1, &clock), Ok(()));
+        assert_eq!(
+            check_bridge_stale(100 - BRIDGE_STALE_AFTER_SLOTS_ELAPSED, &clock),
+            Err(LendingError::BridgeStale.into())
+        );
+        assert_eq!(
+            check_bridge_stale(101, &clock),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+    }
+}
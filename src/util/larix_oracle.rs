@@ -0,0 +1,242 @@
+//! Price accounts of the Larix oracle program, used by reserves that do not use Pyth.
+
+use crate::{
+    error::LendingError,
+    math::Decimal,
+    state::lending_market::LendingMarket,
+    util::pyth::scale_price,
+};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    account_info::AccountInfo,
+    clock::{Clock, Slot},
+    msg,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::{Pubkey, PUBKEY_BYTES},
+};
+
+/// Number of slots after which a Larix oracle price is too old to use
+pub const LARIX_ORACLE_STALE_AFTER_SLOTS_ELAPSED: u64 = 5;
+
+/// Larix oracle price account state
+///
+/// This layout has not been checked against a mainnet account of the Larix oracle program.
+/// Until it is, `load_larix_oracle` only accepts accounts of exactly `LarixOracle::LEN` bytes,
+/// so an account with another layout fails to load instead of producing a price.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LarixOracle {
+    /// Version of the struct, 0 if uninitialized
+    pub version: u8,
+    /// Oracle the price account belongs to, see `LendingMarket::larix_oracle_id`
+    pub oracle_id: Pubkey,
+    /// Price in quote currency, scaled by `10^expo`
+    pub price: u64,
+    /// Price exponent
+    pub expo: i32,
+    /// Slot when the price was last updated
+    pub last_update_slot: Slot,
+}
+
+impl LarixOracle {
+    /// Price in quote currency
+    pub fn price(&self) -> Result<Decimal, ProgramError> {
+        scale_price(self.price, self.expo)
+    }
+}
+
+impl Sealed for LarixOracle {}
+impl IsInitialized for LarixOracle {
+    fn is_initialized(&self) -> bool {
+        self.version != 0
+    }
+}
+
+const LARIX_ORACLE_LEN: usize = 53; // 1 + 32 + 8 + 4 + 8
+impl Pack for LarixOracle {
+    const LEN: usize = LARIX_ORACLE_LEN;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, LARIX_ORACLE_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            oracle_id,
+            price,
+            expo,
+            last_update_slot,
+        ) = mut_array_refs![output, 1, PUBKEY_BYTES, 8, 4, 8];
+
+        *version = self.version.to_le_bytes();
+        oracle_id.copy_from_slice(self.oracle_id.as_ref());
+        *price = self.price.to_le_bytes();
+        *expo = self.expo.to_le_bytes();
+        *last_update_slot = self.last_update_slot.to_le_bytes();
+    }
+
+    /// Unpacks a byte buffer into a [LarixOracle](struct.LarixOracle.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, LARIX_ORACLE_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            oracle_id,
+            price,
+            expo,
+            last_update_slot,
+        ) = array_refs![input, 1, PUBKEY_BYTES, 8, 4, 8];
+
+        Ok(Self {
+            version: u8::from_le_bytes(*version),
+            oracle_id: Pubkey::new_from_array(*oracle_id),
+            price: u64::from_le_bytes(*price),
+            expo: i32::from_le_bytes(*expo),
+            last_update_slot: u64::from_le_bytes(*last_update_slot),
+        })
+    }
+}
+
+/// Load a Larix oracle price account, checking its size and initialization
+pub fn load_larix_oracle(data: &[u8]) -> Result<LarixOracle, ProgramError> {
+    if data.len() != LarixOracle::LEN {
+        msg!("Larix oracle account data size does not match the expected layout");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    let oracle = LarixOracle::unpack_from_slice(data)?;
+    if !oracle.is_initialized() {
+        msg!("Larix oracle account is not initialized");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    Ok(oracle)
+}
+
+/// Read the price of a Larix oracle price account of the lending market in quote currency
+pub fn get_larix_oracle_price(
+    oracle_account: &AccountInfo,
+    lending_market: &LendingMarket,
+    clock: &Clock,
+) -> Result<Decimal, ProgramError> {
+    if oracle_account.owner != &lending_market.larix_oracle_program_id {
+        msg!("Larix oracle account is not owned by the Larix oracle program");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    let oracle = load_larix_oracle(&oracle_account.try_borrow_data()?)?;
+    if oracle.oracle_id != lending_market.larix_oracle_id {
+        msg!("Larix oracle account does not belong to the lending market oracle");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    let slots_elapsed = clock
+        .slot
+        .checked_sub(oracle.last_update_slot)
+        .ok_or_else(|| {
+            msg!("Larix oracle price update slot is ahead of the current slot");
+            LendingError::InvalidOracleConfig
+        })?;
+    if slots_elapsed >= LARIX_ORACLE_STALE_AFTER_SLOTS_ELAPSED {
+        msg!("Larix oracle price is stale");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    if oracle.price == 0 {
+        msg!("Larix oracle price cannot be zero");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    oracle.price()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn get_larix_oracle_price_checks_oracle() {
+        let lending_market = LendingMarket {
+            larix_oracle_program_id: Pubkey::new_unique(),
+            larix_oracle_id: Pubkey::new_unique(),
+            ..LendingMarket::default()
+        };
+        let oracle = LarixOracle {
+            version: 1,
+            oracle_id: lending_market.larix_oracle_id,
+            price: 2_500_000,
+            expo: -6,
+            last_update_slot: 100,
+        };
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![0u8; LarixOracle::LEN];
+        LarixOracle::pack(oracle, &mut data).unwrap();
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &lending_market.larix_oracle_program_id,
+            false,
+            0,
+        );
+
+        let clock = Clock {
+            slot: 101,
+            ..Clock::default()
+        };
+        assert_eq!(
+            get_larix_oracle_price(&account, &lending_market, &clock),
+            Ok(Decimal::from_scaled_val(2_500_000_000_000_000_000))
+        );
+
+        let stale = Clock {
+            slot: 100 + LARIX_ORACLE_STALE_AFTER_SLOTS_ELAPSED,
+            ..Clock::default()
+        };
+        assert_eq!(
+            get_larix_oracle_price(&account, &lending_market, &stale),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+
+        let future = Clock {
+            slot: 99,
+            ..Clock::default()
+        };
+        assert_eq!(
+            get_larix_oracle_price(&account, &lending_market, &future),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+
+        let other_market = LendingMarket {
+            larix_oracle_id: Pubkey::new_unique(),
+            ..lending_market.clone()
+        };
+        assert_eq!(
+            get_larix_oracle_price(&account, &other_market, &clock),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+
+        let zero_price = LarixOracle {
+            price: 0,
+            ..LarixOracle::unpack_from_slice(&account.try_borrow_data().unwrap()).unwrap()
+        };
+        LarixOracle::pack(zero_price, &mut account.try_borrow_mut_data().unwrap()).unwrap();
+        assert_eq!(
+            get_larix_oracle_price(&account, &lending_market, &clock),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+    }
+
+    #[test]
+    fn load_larix_oracle_rejects_unexpected_data() {
+        assert_eq!(
+            load_larix_oracle(&[1u8; LARIX_ORACLE_LEN - 1]),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+        assert_eq!(
+            load_larix_oracle(&[1u8; LARIX_ORACLE_LEN + 1]),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+        assert_eq!(
+            load_larix_oracle(&[0u8; LARIX_ORACLE_LEN]),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+    }
+}
@@ -0,0 +1,355 @@
+//! Fair pricing of constant product AMM LP tokens.
+//!
+//! Valuing an LP token at `(coin_amount * coin_price + pc_amount * pc_price) / lp_supply`
+//! trusts the AMM spot ratio, which can be pushed around within a transaction. The fair
+//! price instead only trusts the invariant `coin_amount * pc_amount` and the oracle prices:
+//!
+//!   fair_price = 2 * sqrt(coin_amount * coin_price * pc_amount * pc_price) / lp_supply
+//!
+//! which is never above the spot price and equal to it when the pool is at the oracle ratio.
+//! The product of the two side values must stay below about 6 * 10^21 to fit in a `Decimal`.
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, TryAdd, TryDiv, TryMul},
+    util::{
+        bridge::{check_bridge_stale, load_bridge_pool, BridgePool},
+        price_source::PriceSource,
+        pyth::get_pyth_price_and_confidence,
+    },
+};
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    msg,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use spl_token::state::{Account, Mint};
+
+/// Token amounts of a constant product AMM
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LpPoolState {
+    /// Amount of coin tokens in the pool
+    pub coin_amount: u64,
+    /// Coin token mint decimals
+    pub coin_decimals: u8,
+    /// Amount of pc tokens in the pool
+    pub pc_amount: u64,
+    /// Pc token mint decimals
+    pub pc_decimals: u8,
+    /// LP token mint supply
+    pub lp_supply: u64,
+    /// LP token mint decimals
+    pub lp_decimals: u8,
+}
+
+impl LpPoolState {
+    /// Value of the coin and pc sides of the pool in quote currency
+    fn side_values(
+        &self,
+        coin_price: Decimal,
+        pc_price: Decimal,
+    ) -> Result<(Decimal, Decimal), ProgramError> {
+        Ok((
+            to_ui_amount(self.coin_amount, self.coin_decimals)?.try_mul(coin_price)?,
+            to_ui_amount(self.pc_amount, self.pc_decimals)?.try_mul(pc_price)?,
+        ))
+    }
+
+    fn lp_supply(&self) -> Result<Decimal, ProgramError> {
+        if self.lp_supply == 0 {
+            msg!("LP token supply is zero");
+            return Err(LendingError::InvalidOracleConfig.into());
+        }
+        to_ui_amount(self.lp_supply, self.lp_decimals)
+    }
+
+    /// Price of one LP token in quote currency, from the pool invariant and oracle prices
+    pub fn fair_price(&self, coin_price: Decimal, pc_price: Decimal) -> Result<Decimal, ProgramError> {
+        let (coin_value, pc_value) = self.side_values(coin_price, pc_price)?;
+        coin_value
+            .try_mul(pc_value)?
+            .try_sqrt()?
+            .try_mul(2u64)?
+            .try_div(self.lp_supply()?)
+    }
+
+    /// Price of one LP token in quote currency, from the current pool amounts
+    pub fn spot_price(&self, coin_price: Decimal, pc_price: Decimal) -> Result<Decimal, ProgramError> {
+        let (coin_value, pc_value) = self.side_values(coin_price, pc_price)?;
+        coin_value.try_add(pc_value)?.try_div(self.lp_supply()?)
+    }
+}
+
+fn to_ui_amount(amount: u64, decimals: u8) -> Result<Decimal, ProgramError> {
+    let decimals = 10u64
+        .checked_pow(decimals as u32)
+        .ok_or(LendingError::MathOverflow)?;
+    Decimal::from(amount).try_div(decimals)
+}
+
+/// Fair LP token price of a bridge pool, from the AMM token accounts and the Pyth prices of
+/// the AMM tokens
+///
+/// Not used by `reserve_price_source`: LP reserves are still priced from the LP price account
+/// of their bridge pool, this source is for callers comparing that price with the fair one.
+pub struct FairLpPriceSource<'a, 'b> {
+    /// Bridge pool account, `ReserveLiquidity::params_1` of the LP reserve
+    pub bridge_pool: &'a AccountInfo<'b>,
+    /// AMM coin token supply account
+    pub amm_coin_mint_supply: &'a AccountInfo<'b>,
+    /// AMM pc token supply account
+    pub amm_pc_mint_supply: &'a AccountInfo<'b>,
+    /// LP token mint
+    pub lp_mint: &'a AccountInfo<'b>,
+    /// Coin token mint, the mint of the AMM coin token supply account
+    pub coin_mint: &'a AccountInfo<'b>,
+    /// Pc token mint, the mint of the AMM pc token supply account
+    pub pc_mint: &'a AccountInfo<'b>,
+    /// Pyth price account of the coin token
+    pub coin_price: &'a AccountInfo<'b>,
+    /// Pyth price account of the pc token
+    pub pc_price: &'a AccountInfo<'b>,
+}
+
+impl FairLpPriceSource<'_, '_> {
+    /// Read the pool amounts and mint decimals, checking the accounts against the bridge pool
+    pub fn pool_state(&self, clock: &Clock) -> Result<LpPoolState, ProgramError> {
+        self.load_pool_state(&self.load_bridge_pool(clock)?)
+    }
+
+    fn load_bridge_pool(&self, clock: &Clock) -> Result<BridgePool, ProgramError> {
+        let bridge_pool = load_bridge_pool(&self.bridge_pool.try_borrow_data()?)?;
+        check_bridge_stale(bridge_pool.last_update_slot, clock)?;
+        Ok(bridge_pool)
+    }
+
+    fn load_pool_state(&self, bridge_pool: &BridgePool) -> Result<LpPoolState, ProgramError> {
+        check_key(self.amm_coin_mint_supply.key, &bridge_pool.amm_coin_mint_supply)?;
+        check_key(self.amm_pc_mint_supply.key, &bridge_pool.amm_pc_mint_supply)?;
+        check_key(self.lp_mint.key, &bridge_pool.lp_mint)?;
+
+        let coin_supply = Account::unpack(&self.amm_coin_mint_supply.try_borrow_data()?)?;
+        let pc_supply = Account::unpack(&self.amm_pc_mint_supply.try_borrow_data()?)?;
+        check_mint(self.coin_mint.key, &coin_supply.mint)?;
+        check_mint(self.pc_mint.key, &pc_supply.mint)?;
+        let coin_mint = Mint::unpack(&self.coin_mint.try_borrow_data()?)?;
+        let pc_mint = Mint::unpack(&self.pc_mint.try_borrow_data()?)?;
+        let lp_mint = Mint::unpack(&self.lp_mint.try_borrow_data()?)?;
+        Ok(LpPoolState {
+            coin_amount: coin_supply.amount,
+            coin_decimals: coin_mint.decimals,
+            pc_amount: pc_supply.amount,
+            pc_decimals: pc_mint.decimals,
+            lp_supply: lp_mint.supply,
+            lp_decimals: lp_mint.decimals,
+        })
+    }
+}
+
+impl PriceSource for FairLpPriceSource<'_, '_> {
+    fn price_and_confidence(&self, clock: &Clock) -> Result<(Decimal, Decimal), ProgramError> {
+        let bridge_pool = self.load_bridge_pool(clock)?;
+        check_key(self.coin_price.key, &bridge_pool.coin_mint_price)?;
+        check_key(self.pc_price.key, &bridge_pool.pc_mint_price)?;
+        let pool = self.load_pool_state(&bridge_pool)?;
+        let (coin_price, coin_confidence) = get_pyth_price_and_confidence(self.coin_price, clock)?;
+        let (pc_price, pc_confidence) = get_pyth_price_and_confidence(self.pc_price, clock)?;
+        let price = pool.fair_price(coin_price, pc_price)?;
+        // The fair price scales with the square root of each price, so its relative confidence
+        // is half the sum of the relative confidences of the two prices
+        let relative_confidence = coin_confidence
+            .try_div(coin_price)?
+            .try_add(pc_confidence.try_div(pc_price)?)?
+            .try_div(2u64)?;
+        Ok((price, price.try_mul(relative_confidence)?))
+    }
+}
+
+fn check_key(key: &Pubkey, expected: &Pubkey) -> Result<(), ProgramError> {
+    if key != expected {
+        msg!("Account provided does not match the bridge pool");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    Ok(())
+}
+
+fn check_mint(key: &Pubkey, expected: &Pubkey) -> Result<(), ProgramError> {
+    if key != expected {
+        msg!("Mint provided does not match the AMM token supply account");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::pyth::{AccountType, Price, PriceStatus, MAGIC, VERSION_2};
+    use bytemuck::{bytes_of, Zeroable};
+    use solana_program::program_option::COption;
+    use spl_token::state::AccountState;
+
+    fn mint_data(supply: u64, decimals: u8) -> Vec<u8> {
+        let mut data = vec![0; Mint::LEN];
+        let mint = Mint {
+            mint_authority: COption::None,
+            supply,
+            decimals,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        };
+        Mint::pack(mint, &mut data).unwrap();
+        data
+    }
+
+    fn token_account_data(mint: Pubkey, amount: u64) -> Vec<u8> {
+        let mut data = vec![0; Account::LEN];
+        let account = Account {
+            mint,
+            amount,
+            state: AccountState::Initialized,
+            ..Account::default()
+        };
+        Account::pack(account, &mut data).unwrap();
+        data
+    }
+
+    fn price_data(price: i64, conf: u64) -> Vec<u8> {
+        let mut account = Price::zeroed();
+        account.magic = MAGIC;
+        account.ver = VERSION_2;
+        account.atype = AccountType::Price as u32;
+        account.expo = -2;
+        account.valid_slot = 10;
+        account.agg.status = PriceStatus::Trading;
+        account.agg.price = price;
+        account.agg.conf = conf;
+        bytes_of(&account).to_vec()
+    }
+
+    #[test]
+    fn pool_state_reads_mint_decimals() {
+        let keys: Vec<Pubkey> = (0..8).map(|_| Pubkey::new_unique()).collect();
+        let (bridge_key, coin_supply_key, pc_supply_key, lp_mint_key, coin_mint_key, pc_mint_key) =
+            (keys[0], keys[1], keys[2], keys[3], keys[4], keys[5]);
+        let (coin_price_key, pc_price_key) = (keys[6], keys[7]);
+        let bridge_pool = BridgePool {
+            version: 1,
+            lp_mint: lp_mint_key,
+            amm_coin_mint_supply: coin_supply_key,
+            amm_pc_mint_supply: pc_supply_key,
+            coin_mint_price: coin_price_key,
+            pc_mint_price: pc_price_key,
+            last_update_slot: 10,
+            ..BridgePool::default()
+        };
+        let mut bridge_data = vec![0; BridgePool::LEN];
+        BridgePool::pack(bridge_pool, &mut bridge_data).unwrap();
+        let mut coin_supply_data = token_account_data(coin_mint_key, 100_000_000_000);
+        let mut pc_supply_data = token_account_data(pc_mint_key, 200_000_000);
+        let mut lp_mint_data = mint_data(10_000_000, 6);
+        let mut coin_mint_data = mint_data(0, 9);
+        let mut pc_mint_data = mint_data(0, 6);
+        let mut other_mint_data = mint_data(0, 9);
+        let other_mint_key = Pubkey::new_unique();
+        // 2 +- 1% and 1 +- 3%
+        let mut coin_price_data = price_data(200, 2);
+        let mut pc_price_data = price_data(100, 3);
+        let owner = Pubkey::new_unique();
+        let mut lamports = [0u64; 9];
+        let mut lamports = lamports.iter_mut();
+        let mut account = |key, data| {
+            AccountInfo::new(key, false, false, lamports.next().unwrap(), data, &owner, false, 0)
+        };
+        let bridge_pool = account(&bridge_key, &mut bridge_data);
+        let amm_coin_mint_supply = account(&coin_supply_key, &mut coin_supply_data);
+        let amm_pc_mint_supply = account(&pc_supply_key, &mut pc_supply_data);
+        let lp_mint = account(&lp_mint_key, &mut lp_mint_data);
+        let coin_mint = account(&coin_mint_key, &mut coin_mint_data);
+        let pc_mint = account(&pc_mint_key, &mut pc_mint_data);
+        let other_mint = account(&other_mint_key, &mut other_mint_data);
+        let coin_price = account(&coin_price_key, &mut coin_price_data);
+        let pc_price = account(&pc_price_key, &mut pc_price_data);
+
+        let mut source = FairLpPriceSource {
+            bridge_pool: &bridge_pool,
+            amm_coin_mint_supply: &amm_coin_mint_supply,
+            amm_pc_mint_supply: &amm_pc_mint_supply,
+            lp_mint: &lp_mint,
+            coin_mint: &coin_mint,
+            pc_mint: &pc_mint,
+            coin_price: &coin_price,
+            pc_price: &pc_price,
+        };
+        let clock = Clock {
+            slot: 11,
+            ..Clock::default()
+        };
+        // Relative confidence of (1% + 3%) / 2
+        assert_eq!(
+            source.price_and_confidence(&clock),
+            Ok((Decimal::from(40u64), Decimal::from_percent(80)))
+        );
+
+        // Price accounts swapped, not the ones of the bridge pool
+        source.coin_price = &pc_price;
+        source.pc_price = &coin_price;
+        assert_eq!(
+            source.price(&clock),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+        source.coin_price = &coin_price;
+        source.pc_price = &pc_price;
+
+        // pc mint that is not the mint of the AMM pc supply account
+        source.pc_mint = &other_mint;
+        assert_eq!(
+            source.price(&clock),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+    }
+
+    #[test]
+    fn fair_price_of_balanced_pool_equals_spot_price() {
+        let pool = LpPoolState {
+            coin_amount: 100_000_000_000,
+            coin_decimals: 9,
+            pc_amount: 200_000_000,
+            pc_decimals: 6,
+            lp_supply: 10_000_000,
+            lp_decimals: 6,
+        };
+        let coin_price = Decimal::from(2u64);
+        let pc_price = Decimal::one();
+        assert_eq!(pool.fair_price(coin_price, pc_price), Ok(Decimal::from(40u64)));
+        assert_eq!(pool.spot_price(coin_price, pc_price), Ok(Decimal::from(40u64)));
+    }
+
+    #[test]
+    fn fair_price_ignores_skewed_pool() {
+        // Same invariant as the balanced pool, skewed by a large coin deposit
+        let pool = LpPoolState {
+            coin_amount: 400_000_000_000,
+            coin_decimals: 9,
+            pc_amount: 50_000_000,
+            pc_decimals: 6,
+            lp_supply: 10_000_000,
+            lp_decimals: 6,
+        };
+        let coin_price = Decimal::from(2u64);
+        let pc_price = Decimal::one();
+        assert_eq!(pool.fair_price(coin_price, pc_price), Ok(Decimal::from(40u64)));
+        assert_eq!(pool.spot_price(coin_price, pc_price), Ok(Decimal::from(85u64)));
+    }
+
+    #[test]
+    fn fair_price_rejects_empty_supply() {
+        assert_eq!(
+            LpPoolState::default().fair_price(Decimal::one(), Decimal::one()),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+    }
+}
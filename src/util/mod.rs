@@ -1,2 +1,7 @@
+pub mod bridge;
+pub mod larix_oracle;
+pub mod lp_price;
+pub mod oracle_failover;
+pub mod price_source;
 pub mod pyth;
 pub mod unpack_util;
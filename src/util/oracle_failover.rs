@@ -0,0 +1,339 @@
+//! Failover between the Pyth and Larix oracle prices of a reserve.
+//!
+//! Non-LP reserves store both a Pyth price account (`params_1`) and a Larix oracle price
+//! account (`params_2`). `use_pyth_oracle` selects the primary one, the other one is used
+//! when the primary is unavailable, so that a halted feed does not freeze the reserve.
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, Rate, TryMul, TrySub},
+    state::{lending_market::LendingMarket, reserve::Reserve},
+    util::price_source::{
+        find_account, LarixOraclePriceSource, PriceSource, PythPriceSource, ReservePriceSource,
+    },
+};
+use solana_program::{account_info::AccountInfo, clock::Clock, msg, program_error::ProgramError};
+
+/// Behavior when neither the primary nor the fallback source returns a price
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BothUnavailable {
+    /// Fail with the error of the primary source
+    Reject,
+    /// Keep the last market price of the reserve and its confidence while they are younger
+    /// than the reserve staleness window, marking the reserve stale
+    KeepLastPrice,
+}
+
+/// Oracle failover policy
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FailoverPolicy {
+    /// Max relative difference between the primary and fallback prices, zero to disable
+    pub max_divergence: Rate,
+    /// Behavior when both sources are unavailable
+    pub both_unavailable: BothUnavailable,
+}
+
+impl Default for FailoverPolicy {
+    fn default() -> Self {
+        Self {
+            max_divergence: Rate::from_percent(5),
+            both_unavailable: BothUnavailable::Reject,
+        }
+    }
+}
+
+/// Why a price was chosen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FailoverReason {
+    /// Both sources available and within the max divergence
+    Primary = 0,
+    /// Primary available, fallback unavailable or not configured
+    PrimaryOnly = 1,
+    /// Primary unavailable, fallback used
+    Fallback = 2,
+    /// Both unavailable, last market price of the reserve kept
+    LastPrice = 3,
+}
+
+/// Price chosen by the failover policy
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FailoverPrice {
+    /// Price in quote currency
+    pub price: Decimal,
+    /// Confidence interval of the price
+    pub confidence: Decimal,
+    /// Why the price was chosen
+    pub reason: FailoverReason,
+}
+
+impl FailoverPolicy {
+    /// Choose a price between a primary and an optional fallback source
+    pub fn select_price(
+        &self,
+        primary: &impl PriceSource,
+        fallback: Option<&impl PriceSource>,
+        reserve: &Reserve,
+        lending_market: &LendingMarket,
+        clock: &Clock,
+    ) -> Result<FailoverPrice, ProgramError> {
+        let primary = primary.price_and_confidence(clock);
+        let fallback = fallback.map(|fallback| fallback.price_and_confidence(clock));
+        match (primary, fallback) {
+            (Ok((price, confidence)), Some(Ok((fallback_price, _)))) => {
+                self.check_divergence(price, fallback_price)?;
+                Ok(FailoverPrice {
+                    price,
+                    confidence,
+                    reason: FailoverReason::Primary,
+                })
+            }
+            (Ok((price, confidence)), _) => Ok(FailoverPrice {
+                price,
+                confidence,
+                reason: FailoverReason::PrimaryOnly,
+            }),
+            (Err(_), Some(Ok((price, confidence)))) => {
+                msg!("Primary oracle unavailable, using fallback oracle");
+                Ok(FailoverPrice {
+                    price,
+                    confidence,
+                    reason: FailoverReason::Fallback,
+                })
+            }
+            (Err(error), _) => {
+                let liquidity = &reserve.liquidity;
+                let last_price_age = clock.slot.saturating_sub(liquidity.market_price_slot);
+                if self.both_unavailable == BothUnavailable::KeepLastPrice
+                    && liquidity.market_price != Decimal::zero()
+                    && last_price_age < reserve.stale_after_slots_elapsed(lending_market)
+                {
+                    msg!("Oracles unavailable, keeping last price");
+                    Ok(FailoverPrice {
+                        price: liquidity.market_price,
+                        confidence: liquidity.market_price_confidence,
+                        reason: FailoverReason::LastPrice,
+                    })
+                } else {
+                    Err(error)
+                }
+            }
+        }
+    }
+
+    fn check_divergence(&self, price: Decimal, other: Decimal) -> Result<(), ProgramError> {
+        if self.max_divergence == Rate::zero() {
+            return Ok(());
+        }
+        let difference = if price > other {
+            price.try_sub(other)?
+        } else {
+            other.try_sub(price)?
+        };
+        if difference > price.try_mul(self.max_divergence)? {
+            msg!("Primary and fallback oracle prices diverge");
+            return Err(LendingError::OraclePriceDivergence.into());
+        }
+        Ok(())
+    }
+
+    /// Refresh a reserve like `Reserve::refresh`, with the price chosen by the policy
+    ///
+    /// A kept last price is left in place and the reserve is marked stale, so that it cannot
+    /// back borrows or liquidations until an oracle returns a price again.
+    pub fn refresh_reserve(
+        &self,
+        reserve: &mut Reserve,
+        lending_market: &LendingMarket,
+        current_borrow_rate: Rate,
+        primary: &impl PriceSource,
+        fallback: Option<&impl PriceSource>,
+        clock: &Clock,
+    ) -> Result<FailoverReason, ProgramError> {
+        reserve.accrue_interest(current_borrow_rate, clock)?;
+        let chosen = self.select_price(primary, fallback, reserve, lending_market, clock)?;
+        if chosen.reason == FailoverReason::LastPrice {
+            reserve.last_update.mark_stale();
+        } else {
+            reserve.update_market_price(chosen.price, chosen.confidence, clock)?;
+        }
+        Ok(chosen.reason)
+    }
+}
+
+/// Primary and fallback price sources of a non-LP reserve among the oracle accounts provided,
+/// the fallback is `None` when its account is not provided
+pub fn reserve_failover_sources<'a, 'b>(
+    reserve: &Reserve,
+    lending_market: &'a LendingMarket,
+    oracle_accounts: &'a [AccountInfo<'b>],
+) -> Result<
+    (
+        ReservePriceSource<'a, 'b>,
+        Option<ReservePriceSource<'a, 'b>>,
+    ),
+    ProgramError,
+> {
+    let liquidity = &reserve.liquidity;
+    if liquidity.is_lp {
+        msg!("LP reserves have no fallback oracle");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    let pyth = |required| {
+        find_account(oracle_accounts, &liquidity.params_1)
+            .map(|price_account| ReservePriceSource::Pyth(PythPriceSource { price_account }))
+            .map(Some)
+            .or_else(|error| if required { Err(error) } else { Ok(None) })
+    };
+    let larix_oracle = |required| {
+        find_account(oracle_accounts, &liquidity.params_2)
+            .map(|oracle_account| {
+                ReservePriceSource::LarixOracle(LarixOraclePriceSource {
+                    oracle_account,
+                    lending_market,
+                })
+            })
+            .map(Some)
+            .or_else(|error| if required { Err(error) } else { Ok(None) })
+    };
+    let (primary, fallback) = if liquidity.use_pyth_oracle {
+        (pyth(true)?, larix_oracle(false)?)
+    } else {
+        (larix_oracle(true)?, pyth(false)?)
+    };
+    Ok((primary.ok_or(LendingError::InvalidOracleConfig)?, fallback))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::price_source::MockPriceSource;
+
+    fn last_price_reserve() -> Reserve {
+        let mut reserve = Reserve::default();
+        reserve.liquidity.market_price = Decimal::from(9u64);
+        reserve.liquidity.market_price_confidence = Decimal::one();
+        reserve.liquidity.market_price_slot = 10;
+        reserve
+    }
+
+    fn select(
+        policy: &FailoverPolicy,
+        primary: &MockPriceSource,
+        fallback: &MockPriceSource,
+    ) -> Result<FailoverPrice, ProgramError> {
+        let clock = Clock {
+            slot: 10,
+            ..Clock::default()
+        };
+        policy.select_price(
+            primary,
+            Some(fallback),
+            &last_price_reserve(),
+            &LendingMarket::default(),
+            &clock,
+        )
+    }
+
+    #[test]
+    fn select_price_fails_over() {
+        let policy = FailoverPolicy::default();
+        let price = MockPriceSource::new(Decimal::from(100u64));
+        let close = MockPriceSource::new(Decimal::from(104u64));
+        let far = MockPriceSource::new(Decimal::from(106u64));
+        let halted = MockPriceSource::failing(LendingError::InvalidOracleConfig.into());
+
+        assert_eq!(
+            select(&policy, &price, &close).map(|chosen| (chosen.price, chosen.reason)),
+            Ok((Decimal::from(100u64), FailoverReason::Primary))
+        );
+        assert_eq!(
+            select(&policy, &price, &far),
+            Err(LendingError::OraclePriceDivergence.into())
+        );
+        assert_eq!(
+            select(&policy, &price, &halted).map(|chosen| chosen.reason),
+            Ok(FailoverReason::PrimaryOnly)
+        );
+        assert_eq!(
+            select(&policy, &halted, &far).map(|chosen| (chosen.price, chosen.reason)),
+            Ok((Decimal::from(106u64), FailoverReason::Fallback))
+        );
+        assert_eq!(
+            select(&policy, &halted, &halted),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+
+        let keep_last_price = FailoverPolicy {
+            both_unavailable: BothUnavailable::KeepLastPrice,
+            ..policy
+        };
+        assert_eq!(
+            select(&keep_last_price, &halted, &halted),
+            Ok(FailoverPrice {
+                price: Decimal::from(9u64),
+                confidence: Decimal::one(),
+                reason: FailoverReason::LastPrice,
+            })
+        );
+    }
+
+    #[test]
+    fn keep_last_price_is_bounded_and_marks_stale() {
+        let policy = FailoverPolicy {
+            both_unavailable: BothUnavailable::KeepLastPrice,
+            ..FailoverPolicy::default()
+        };
+        let lending_market = LendingMarket::default();
+        let halted = MockPriceSource::failing(LendingError::InvalidOracleConfig.into());
+        let price = MockPriceSource::new(Decimal::from(10u64));
+        let mut reserve = last_price_reserve();
+        reserve.last_update.update_slot(10);
+        let stale_after = reserve.stale_after_slots_elapsed(&lending_market);
+        let mut clock = Clock {
+            slot: 10,
+            ..Clock::default()
+        };
+
+        assert_eq!(
+            policy.refresh_reserve(
+                &mut reserve,
+                &lending_market,
+                Rate::zero(),
+                &halted,
+                Some(&halted),
+                &clock
+            ),
+            Ok(FailoverReason::LastPrice)
+        );
+        assert!(reserve.last_update.stale);
+        assert_eq!(reserve.liquidity.market_price, Decimal::from(9u64));
+
+        clock.slot = 10 + stale_after;
+        assert_eq!(
+            policy.refresh_reserve(
+                &mut reserve,
+                &lending_market,
+                Rate::zero(),
+                &halted,
+                Some(&halted),
+                &clock
+            ),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+
+        assert_eq!(
+            policy.refresh_reserve(
+                &mut reserve,
+                &lending_market,
+                Rate::zero(),
+                &price,
+                Some(&halted),
+                &clock
+            ),
+            Ok(FailoverReason::PrimaryOnly)
+        );
+        assert_eq!(reserve.liquidity.market_price, Decimal::from(10u64));
+        assert_eq!(reserve.liquidity.market_price_slot, clock.slot);
+    }
+}
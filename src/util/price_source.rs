@@ -0,0 +1,240 @@
+//! Sources of reserve liquidity market prices.
+//!
+//! The meaning of `ReserveLiquidity::params_1` and `params_2` depends on the reserve flags:
+//!   - `is_lp`: `params_1` is a bridge pool and `params_2` its LP price account
+//!   - `use_pyth_oracle`: `params_1` is a Pyth price account
+//!   - otherwise: `params_2` is a Larix oracle price account
+//!
+//! LP reserves are priced from the LP price account of their bridge pool. The fair LP price
+//! of `util::lp_price` is not wired into `reserve_price_source`.
+
+use crate::{
+    error::LendingError,
+    math::Decimal,
+    state::{lending_market::LendingMarket, reserve::Reserve},
+    util::{
+        bridge::{check_bridge_stale, load_lp_price},
+        larix_oracle::get_larix_oracle_price,
+        pyth::get_pyth_price_and_confidence,
+    },
+};
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+/// Source of a market price in quote currency
+pub trait PriceSource {
+    /// Current price and its confidence interval
+    fn price_and_confidence(&self, clock: &Clock) -> Result<(Decimal, Decimal), ProgramError>;
+
+    /// Current price
+    fn price(&self, clock: &Clock) -> Result<Decimal, ProgramError> {
+        Ok(self.price_and_confidence(clock)?.0)
+    }
+}
+
+/// Pyth price account
+pub struct PythPriceSource<'a, 'b> {
+    /// Pyth price account
+    pub price_account: &'a AccountInfo<'b>,
+}
+
+impl PriceSource for PythPriceSource<'_, '_> {
+    fn price_and_confidence(&self, clock: &Clock) -> Result<(Decimal, Decimal), ProgramError> {
+        get_pyth_price_and_confidence(self.price_account, clock)
+    }
+}
+
+/// Larix oracle price account
+pub struct LarixOraclePriceSource<'a, 'b> {
+    /// Larix oracle price account
+    pub oracle_account: &'a AccountInfo<'b>,
+    /// Lending market the oracle is configured for
+    pub lending_market: &'a LendingMarket,
+}
+
+impl PriceSource for LarixOraclePriceSource<'_, '_> {
+    fn price_and_confidence(&self, clock: &Clock) -> Result<(Decimal, Decimal), ProgramError> {
+        let price = get_larix_oracle_price(self.oracle_account, self.lending_market, clock)?;
+        Ok((price, Decimal::zero()))
+    }
+}
+
+/// LP price account of a bridge pool
+pub struct BridgeLpPriceSource<'a, 'b> {
+    /// LP price account
+    pub lp_price_account: &'a AccountInfo<'b>,
+    /// Bridge pool the LP price must belong to
+    pub bridge_pool: Pubkey,
+}
+
+impl PriceSource for BridgeLpPriceSource<'_, '_> {
+    fn price_and_confidence(&self, clock: &Clock) -> Result<(Decimal, Decimal), ProgramError> {
+        let lp_price = load_lp_price(&self.lp_price_account.try_borrow_data()?)?;
+        if lp_price.bridge_pool != self.bridge_pool {
+            msg!("LP price account does not belong to the reserve bridge pool");
+            return Err(LendingError::InvalidOracleConfig.into());
+        }
+        check_bridge_stale(lp_price.last_update_slot, clock)?;
+        Ok((lp_price.price, Decimal::zero()))
+    }
+}
+
+/// Fixed price, for tests and simulations
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockPriceSource {
+    /// Price or error returned by the source
+    pub result: Result<Decimal, ProgramError>,
+    /// Confidence interval returned along with the price
+    pub confidence: Decimal,
+}
+
+impl MockPriceSource {
+    /// Create a source always returning a price
+    pub fn new(price: Decimal) -> Self {
+        Self {
+            result: Ok(price),
+            confidence: Decimal::zero(),
+        }
+    }
+
+    /// Create a source always failing with an error
+    pub fn failing(error: ProgramError) -> Self {
+        Self {
+            result: Err(error),
+            confidence: Decimal::zero(),
+        }
+    }
+}
+
+impl PriceSource for MockPriceSource {
+    fn price_and_confidence(&self, _clock: &Clock) -> Result<(Decimal, Decimal), ProgramError> {
+        Ok((self.result.clone()?, self.confidence))
+    }
+}
+
+/// Price source configured for a reserve
+pub enum ReservePriceSource<'a, 'b> {
+    /// Pyth price account
+    Pyth(PythPriceSource<'a, 'b>),
+    /// Larix oracle price account
+    LarixOracle(LarixOraclePriceSource<'a, 'b>),
+    /// LP price account of a bridge pool
+    BridgeLp(BridgeLpPriceSource<'a, 'b>),
+}
+
+impl PriceSource for ReservePriceSource<'_, '_> {
+    fn price_and_confidence(&self, clock: &Clock) -> Result<(Decimal, Decimal), ProgramError> {
+        match self {
+            Self::Pyth(source) => source.price_and_confidence(clock),
+            Self::LarixOracle(source) => source.price_and_confidence(clock),
+            Self::BridgeLp(source) => source.price_and_confidence(clock),
+        }
+    }
+}
+
+/// Select the price source of a reserve among the oracle accounts provided
+pub fn reserve_price_source<'a, 'b>(
+    reserve: &Reserve,
+    lending_market: &'a LendingMarket,
+    oracle_accounts: &'a [AccountInfo<'b>],
+) -> Result<ReservePriceSource<'a, 'b>, ProgramError> {
+    let liquidity = &reserve.liquidity;
+    if liquidity.is_lp {
+        Ok(ReservePriceSource::BridgeLp(BridgeLpPriceSource {
+            lp_price_account: find_account(oracle_accounts, &liquidity.params_2)?,
+            bridge_pool: liquidity.params_1,
+        }))
+    } else if liquidity.use_pyth_oracle {
+        Ok(ReservePriceSource::Pyth(PythPriceSource {
+            price_account: find_account(oracle_accounts, &liquidity.params_1)?,
+        }))
+    } else {
+        Ok(ReservePriceSource::LarixOracle(LarixOraclePriceSource {
+            oracle_account: find_account(oracle_accounts, &liquidity.params_2)?,
+            lending_market,
+        }))
+    }
+}
+
+pub(crate) fn find_account<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    key: &Pubkey,
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    accounts
+        .iter()
+        .find(|account| account.key == key)
+        .ok_or_else(|| {
+            msg!("Oracle account provided does not match the reserve oracle config");
+            LendingError::InvalidOracleConfig.into()
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reserve_price_source_selects_oracle_account() {
+        let pyth_key = Pubkey::new_unique();
+        let larix_key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let (mut pyth_lamports, mut larix_lamports) = (0, 0);
+        let (mut pyth_data, mut larix_data) = (vec![], vec![]);
+        let oracle_accounts = [
+            AccountInfo::new(&pyth_key, false, false, &mut pyth_lamports, &mut pyth_data, &owner, false, 0),
+            AccountInfo::new(&larix_key, false, false, &mut larix_lamports, &mut larix_data, &owner, false, 0),
+        ];
+        let lending_market = LendingMarket::default();
+        let mut reserve = Reserve::default();
+        reserve.liquidity.params_1 = pyth_key;
+        reserve.liquidity.params_2 = larix_key;
+
+        reserve.liquidity.use_pyth_oracle = true;
+        match reserve_price_source(&reserve, &lending_market, &oracle_accounts).unwrap() {
+            ReservePriceSource::Pyth(source) => assert_eq!(source.price_account.key, &pyth_key),
+            _ => panic!("expected a Pyth price source"),
+        }
+
+        reserve.liquidity.use_pyth_oracle = false;
+        match reserve_price_source(&reserve, &lending_market, &oracle_accounts).unwrap() {
+            ReservePriceSource::LarixOracle(source) => assert_eq!(source.oracle_account.key, &larix_key),
+            _ => panic!("expected a Larix oracle price source"),
+        }
+
+        reserve.liquidity.is_lp = true;
+        match reserve_price_source(&reserve, &lending_market, &oracle_accounts).unwrap() {
+            ReservePriceSource::BridgeLp(source) => {
+                assert_eq!(source.lp_price_account.key, &larix_key);
+                assert_eq!(source.bridge_pool, pyth_key);
+            }
+            _ => panic!("expected a bridge LP price source"),
+        }
+
+        reserve.liquidity.params_2 = Pubkey::new_unique();
+        assert!(reserve_price_source(&reserve, &lending_market, &oracle_accounts).is_err());
+    }
+
+    #[test]
+    fn refresh_market_price_from_mock() {
+        let mut reserve = Reserve::default();
+        let clock = Clock::default();
+        reserve
+            .refresh_market_price(&MockPriceSource::new(Decimal::from(7u64)), &clock)
+            .unwrap();
+        assert_eq!(reserve.liquidity.market_price, Decimal::from(7u64));
+
+        assert_eq!(
+            reserve.refresh_market_price(
+                &MockPriceSource::failing(LendingError::InvalidOracleConfig.into()),
+                &clock
+            ),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+        assert_eq!(reserve.liquidity.market_price, Decimal::from(7u64));
+    }
+}
@@ -1,10 +1,11 @@
 #![allow(missing_docs)]
 /// Derived from https://github.com/project-serum/anchor/blob/9224e0fa99093943a6190e396bccbc3387e5b230/examples/pyth/programs/pyth/src/pc.rs
-use bytemuck::{
-    cast_slice, cast_slice_mut, from_bytes, from_bytes_mut, try_cast_slice, try_cast_slice_mut,
-    Pod, PodCastError, Zeroable,
+use bytemuck::{try_from_bytes, try_from_bytes_mut, Pod, PodCastError, Zeroable};
+use crate::{error::LendingError, math::{Decimal, TryDiv, TryMul}};
+use solana_program::{
+    account_info::AccountInfo, clock::Clock, msg, program_error::ProgramError, pubkey::Pubkey,
 };
-use std::mem::size_of;
+use std::{convert::TryFrom, mem::size_of};
 
 pub const MAGIC: u32 = 0xa1b2c3d4;
 pub const VERSION_2: u32 = 2;
@@ -14,12 +15,25 @@ pub const PROD_ACCT_SIZE: usize = 512;
 pub const PROD_HDR_SIZE: usize = 48;
 pub const PROD_ATTR_SIZE: usize = PROD_ACCT_SIZE - PROD_HDR_SIZE;
 pub const USE_PYTH: bool = true;
+/// Number of slots after which an aggregate price is too old to use
+pub const PYTH_STALE_AFTER_SLOTS_ELAPSED: u64 = 5;
 #[derive(Copy, Clone)]
 #[repr(C)]
 pub struct AccKey {
     pub val: [u8; 32],
 }
 
+impl AccKey {
+    /// Key of the referenced account, none if the key is zeroed
+    pub fn pubkey(&self) -> Option<Pubkey> {
+        if self.val == [0u8; 32] {
+            None
+        } else {
+            Some(Pubkey::new_from_array(self.val))
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 #[repr(C)]
 pub enum AccountType {
@@ -29,7 +43,7 @@ pub enum AccountType {
     Price,
 }
 
-#[derive(Copy, Clone)]
+#[derive(PartialEq, Copy, Clone)]
 #[repr(C)]
 pub enum PriceStatus {
     Unknown,
@@ -44,6 +58,25 @@ pub enum CorpAction {
     NoCorpAct,
 }
 
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Mapping {
+    pub magic: u32,                          // pyth magic number
+    pub ver: u32,                            // program version
+    pub atype: u32,                          // account type
+    pub size: u32,                           // account used size
+    pub num: u32,                            // number of product accounts
+    pub unused: u32,
+    pub next: AccKey,                        // next mapping account in linked list
+    pub products: [AccKey; MAP_TABLE_SIZE],  // product accounts
+}
+
+#[cfg(target_endian = "little")]
+unsafe impl Zeroable for Mapping {}
+
+#[cfg(target_endian = "little")]
+unsafe impl Pod for Mapping {}
+
 #[derive(Copy, Clone)]
 #[repr(C)]
 pub struct PriceInfo {
@@ -120,16 +153,479 @@ unsafe impl Zeroable for Product {}
 #[cfg(target_endian = "little")]
 unsafe impl Pod for Product {}
 
+impl Product {
+    /// Iterate over the reference attributes as key/value pairs
+    pub fn iter(&self) -> AttributeIter<'_> {
+        let len = (self.size as usize)
+            .saturating_sub(PROD_HDR_SIZE)
+            .min(PROD_ATTR_SIZE);
+        AttributeIter {
+            attrs: &self.attr[..len],
+        }
+    }
+
+    /// Look up the value of a reference attribute
+    pub fn get_attr(&self, key: &str) -> Option<&str> {
+        self.iter()
+            .find(|(attr_key, _)| *attr_key == key)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Iterator over the length-prefixed key/value pairs of a product account
+pub struct AttributeIter<'a> {
+    attrs: &'a [u8],
+}
+
+impl<'a> Iterator for AttributeIter<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, rest) = get_attr_str(self.attrs)?;
+        let (value, rest) = get_attr_str(rest)?;
+        self.attrs = rest;
+        Some((key, value))
+    }
+}
+
+fn get_attr_str(buf: &[u8]) -> Option<(&str, &[u8])> {
+    let (&len, rest) = buf.split_first()?;
+    let len = len as usize;
+    if len > rest.len() {
+        return None;
+    }
+    let (value, rest) = rest.split_at(len);
+    Some((std::str::from_utf8(value).ok()?, rest))
+}
+
 pub fn load<T: Pod>(data: &[u8]) -> Result<&T, PodCastError> {
     let size = size_of::<T>();
-    Ok(from_bytes(cast_slice::<u8, u8>(try_cast_slice(
-        &data[0..size],
-    )?)))
+    let data = data.get(0..size).ok_or(PodCastError::SizeMismatch)?;
+    try_from_bytes(data)
 }
 
 pub fn load_mut<T: Pod>(data: &mut [u8]) -> Result<&mut T, PodCastError> {
     let size = size_of::<T>();
-    Ok(from_bytes_mut(cast_slice_mut::<u8, u8>(
-        try_cast_slice_mut(&mut data[0..size])?,
-    )))
+    let data = data.get_mut(0..size).ok_or(PodCastError::SizeMismatch)?;
+    try_from_bytes_mut(data)
+}
+
+/// Load a Pyth price account, checking its header
+pub fn load_price_account(data: &[u8]) -> Result<&Price, ProgramError> {
+    let price = load::<Price>(data).map_err(|error| {
+        msg!("Failed to load Pyth price account: {:?}", error);
+        LendingError::InvalidOracleConfig
+    })?;
+    check_header(price.magic, price.ver, price.atype, AccountType::Price)?;
+    Ok(price)
+}
+
+/// Load a Pyth mapping account, checking its header
+pub fn load_mapping_account(data: &[u8]) -> Result<&Mapping, ProgramError> {
+    let mapping = load::<Mapping>(data).map_err(|error| {
+        msg!("Failed to load Pyth mapping account: {:?}", error);
+        LendingError::InvalidOracleConfig
+    })?;
+    check_header(mapping.magic, mapping.ver, mapping.atype, AccountType::Mapping)?;
+    Ok(mapping)
+}
+
+/// Load a Pyth product account, checking its header
+pub fn load_product_account(data: &[u8]) -> Result<&Product, ProgramError> {
+    let product = load::<Product>(data).map_err(|error| {
+        msg!("Failed to load Pyth product account: {:?}", error);
+        LendingError::InvalidOracleConfig
+    })?;
+    check_header(product.magic, product.ver, product.atype, AccountType::Product)?;
+    Ok(product)
+}
+
+/// Read the aggregate price of a Pyth price account in quote currency
+pub fn get_pyth_price(price_account: &AccountInfo, clock: &Clock) -> Result<Decimal, ProgramError> {
+    let (price, _confidence) = get_pyth_price_and_confidence(price_account, clock)?;
+    Ok(price)
+}
+
+/// Read the aggregate price of a Pyth price account and its confidence interval in quote currency
+pub fn get_pyth_price_and_confidence(
+    price_account: &AccountInfo,
+    clock: &Clock,
+) -> Result<(Decimal, Decimal), ProgramError> {
+    let data = price_account.try_borrow_data()?;
+    let price = load_price_account(&data)?;
+
+    if price.agg.status != PriceStatus::Trading {
+        msg!("Pyth price is not trading");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    let slots_elapsed = clock.slot.checked_sub(price.valid_slot).ok_or_else(|| {
+        msg!("Pyth price valid slot is ahead of the current slot");
+        LendingError::InvalidOracleConfig
+    })?;
+    if slots_elapsed >= PYTH_STALE_AFTER_SLOTS_ELAPSED {
+        msg!("Pyth price is stale");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    let agg_price = u64::try_from(price.agg.price).map_err(|_| {
+        msg!("Pyth price cannot be negative");
+        LendingError::InvalidOracleConfig
+    })?;
+    if agg_price == 0 {
+        msg!("Pyth price cannot be zero");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    Ok((
+        scale_price(agg_price, price.expo)?,
+        scale_price(price.agg.conf, price.expo)?,
+    ))
+}
+
+/// Scale a raw oracle price by a power of ten exponent
+pub fn scale_price(price: u64, expo: i32) -> Result<Decimal, ProgramError> {
+    let factor = 10u64
+        .checked_pow(expo.unsigned_abs())
+        .ok_or(LendingError::MathOverflow)?;
+    if expo >= 0 {
+        Decimal::from(price).try_mul(factor)
+    } else {
+        Decimal::from(price).try_div(factor)
+    }
+}
+
+/// Check that a product is quoted in the lending market quote currency
+pub fn check_quote_currency(product: &Product, quote_currency: &[u8; 32]) -> Result<(), ProgramError> {
+    let product_quote_currency = product.get_attr("quote_currency").ok_or_else(|| {
+        msg!("Pyth product has no quote currency attribute");
+        LendingError::NoThisAttribute
+    })?;
+    let product_quote_currency = product_quote_currency.as_bytes();
+    if product_quote_currency.len() > quote_currency.len() {
+        msg!("Pyth product quote currency is too long");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    let mut padded = [0u8; 32];
+    padded[..product_quote_currency.len()].copy_from_slice(product_quote_currency);
+    if &padded != quote_currency {
+        msg!("Pyth product quote currency does not match the lending market");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    Ok(())
+}
+
+/// Max number of accounts visited when following a linked list of Pyth accounts
+pub const MAX_LINKED_ACCOUNTS: usize = 64;
+
+/// Product and price accounts of a Pyth price feed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PriceFeed {
+    /// Pyth product account
+    pub product: Pubkey,
+    /// Pyth price account
+    pub price: Pubkey,
+}
+
+/// List the product accounts of a mapping account and the mapping accounts linked after it
+pub fn get_product_keys<F>(mapping: &Pubkey, mut get_account_data: F) -> Result<Vec<Pubkey>, ProgramError>
+where
+    F: FnMut(&Pubkey) -> Option<Vec<u8>>,
+{
+    let mut product_keys = vec![];
+    let mut next = Some(*mapping);
+    for _ in 0..MAX_LINKED_ACCOUNTS {
+        let key = match next {
+            Some(key) => key,
+            None => return Ok(product_keys),
+        };
+        let data = get_account_data(&key).ok_or_else(|| {
+            msg!("Pyth mapping account not found");
+            LendingError::InvalidAccountInput
+        })?;
+        let mapping = load_mapping_account(&data)?;
+        let num = (mapping.num as usize).min(MAP_TABLE_SIZE);
+        product_keys.extend(mapping.products[..num].iter().filter_map(AccKey::pubkey));
+        next = mapping.next.pubkey();
+    }
+    msg!("Pyth mapping accounts are linked too deep");
+    Err(LendingError::InvalidOracleConfig.into())
+}
+
+/// Find the first price account of a product, following the linked list of price accounts
+pub fn get_price_key<F>(product: &Product, mut get_account_data: F) -> Result<Pubkey, ProgramError>
+where
+    F: FnMut(&Pubkey) -> Option<Vec<u8>>,
+{
+    let mut next = product.px_acc.pubkey();
+    for _ in 0..MAX_LINKED_ACCOUNTS {
+        let key = next.ok_or_else(|| {
+            msg!("Pyth product has no price account");
+            LendingError::InvalidOracleConfig
+        })?;
+        let data = get_account_data(&key).ok_or_else(|| {
+            msg!("Pyth price account not found");
+            LendingError::InvalidAccountInput
+        })?;
+        let price = load_price_account(&data)?;
+        if price.ptype == PriceType::Price {
+            return Ok(key);
+        }
+        next = price.next.pubkey();
+    }
+    msg!("Pyth price accounts are linked too deep");
+    Err(LendingError::InvalidOracleConfig.into())
+}
+
+/// Find the price feed of a symbol such as "Crypto.SOL/USD", starting from a mapping account
+pub fn find_price_feed<F>(
+    mapping: &Pubkey,
+    symbol: &str,
+    mut get_account_data: F,
+) -> Result<Option<PriceFeed>, ProgramError>
+where
+    F: FnMut(&Pubkey) -> Option<Vec<u8>>,
+{
+    for product_key in get_product_keys(mapping, &mut get_account_data)? {
+        let data = get_account_data(&product_key).ok_or_else(|| {
+            msg!("Pyth product account not found");
+            LendingError::InvalidAccountInput
+        })?;
+        let product = load_product_account(&data)?;
+        if product.get_attr("symbol") == Some(symbol) {
+            return Ok(Some(PriceFeed {
+                product: product_key,
+                price: get_price_key(product, &mut get_account_data)?,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+fn check_header(magic: u32, ver: u32, atype: u32, expected: AccountType) -> Result<(), ProgramError> {
+    if magic != MAGIC {
+        msg!("Pyth account has an invalid magic number");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    if ver != VERSION_2 {
+        msg!("Pyth account has an unsupported version");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    if atype != expected as u32 {
+        msg!("Pyth account has an unexpected account type");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bytemuck::bytes_of;
+    use solana_program::pubkey::Pubkey;
+
+    fn price_account() -> Price {
+        let mut price = Price::zeroed();
+        price.magic = MAGIC;
+        price.ver = VERSION_2;
+        price.atype = AccountType::Price as u32;
+        price
+    }
+
+    fn get_price(price: &Price, slot: u64) -> Result<Decimal, ProgramError> {
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = bytes_of(price).to_vec();
+        let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        get_pyth_price(&account, &Clock { slot, ..Clock::default() })
+    }
+
+    #[test]
+    fn get_pyth_price_scales_by_expo() {
+        let mut price = price_account();
+        price.agg.status = PriceStatus::Trading;
+        price.agg.price = 12345;
+        price.valid_slot = 10;
+
+        price.expo = -2;
+        assert_eq!(get_price(&price, 10), Ok(Decimal::from_scaled_val(123_450_000_000_000_000_000)));
+        price.expo = 2;
+        assert_eq!(get_price(&price, 14), Ok(Decimal::from(1_234_500u64)));
+    }
+
+    #[test]
+    fn get_pyth_price_and_confidence_scales_conf() {
+        let mut price = price_account();
+        price.agg.status = PriceStatus::Trading;
+        price.agg.price = 12345;
+        price.agg.conf = 50;
+        price.expo = -2;
+
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = bytes_of(&price).to_vec();
+        let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &key, false, 0);
+        assert_eq!(
+            get_pyth_price_and_confidence(&account, &Clock::default()),
+            Ok((
+                Decimal::from_scaled_val(123_450_000_000_000_000_000),
+                Decimal::from_scaled_val(500_000_000_000_000_000),
+            ))
+        );
+    }
+
+    #[test]
+    fn get_pyth_price_rejects_unusable_prices() {
+        let mut price = price_account();
+        price.agg.status = PriceStatus::Halted;
+        price.agg.price = 100;
+        assert_eq!(get_price(&price, 0), Err(LendingError::InvalidOracleConfig.into()));
+
+        price.agg.status = PriceStatus::Trading;
+        price.agg.price = -100;
+        assert_eq!(get_price(&price, 0), Err(LendingError::InvalidOracleConfig.into()));
+
+        price.agg.price = 0;
+        assert_eq!(get_price(&price, 0), Err(LendingError::InvalidOracleConfig.into()));
+
+        price.agg.price = 100;
+        assert_eq!(
+            get_price(&price, PYTH_STALE_AFTER_SLOTS_ELAPSED),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+
+        price.valid_slot = 10;
+        assert_eq!(get_price(&price, 9), Err(LendingError::InvalidOracleConfig.into()));
+        assert_eq!(get_price(&price, 10), Ok(Decimal::from(100u64)));
+    }
+
+    fn product_account(attrs: &[(&str, &str)]) -> Product {
+        let mut product = Product::zeroed();
+        let mut len = 0;
+        for (key, value) in attrs {
+            for attr in [key, value] {
+                product.attr[len] = attr.len() as u8;
+                product.attr[len + 1..len + 1 + attr.len()].copy_from_slice(attr.as_bytes());
+                len += 1 + attr.len();
+            }
+        }
+        product.size = (PROD_HDR_SIZE + len) as u32;
+        product
+    }
+
+    #[test]
+    fn product_attributes() {
+        let product = product_account(&[("symbol", "Crypto.SOL/USD"), ("quote_currency", "USD")]);
+        assert_eq!(
+            product.iter().collect::<Vec<_>>(),
+            vec![("symbol", "Crypto.SOL/USD"), ("quote_currency", "USD")]
+        );
+        assert_eq!(product.get_attr("symbol"), Some("Crypto.SOL/USD"));
+        assert_eq!(product.get_attr("base"), None);
+
+        let mut usd = [0u8; 32];
+        usd[..3].copy_from_slice(b"USD");
+        assert_eq!(check_quote_currency(&product, &usd), Ok(()));
+        assert_eq!(
+            check_quote_currency(&product, &[0u8; 32]),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+        assert_eq!(
+            check_quote_currency(&product_account(&[]), &usd),
+            Err(LendingError::NoThisAttribute.into())
+        );
+    }
+
+    #[test]
+    fn product_attributes_stop_at_truncated_pair() {
+        let mut product = product_account(&[("symbol", "Crypto.SOL/USD")]);
+        product.attr[0] = 200;
+        assert_eq!(product.iter().count(), 0);
+    }
+
+    #[test]
+    fn find_price_feed_by_symbol() {
+        use std::collections::HashMap;
+
+        let mapping_key = Pubkey::new_unique();
+        let next_mapping_key = Pubkey::new_unique();
+        let sol_key = Pubkey::new_unique();
+        let btc_key = Pubkey::new_unique();
+        let twap_key = Pubkey::new_unique();
+        let price_key = Pubkey::new_unique();
+
+        let mut accounts = HashMap::new();
+        let mut mapping = Mapping::zeroed();
+        mapping.magic = MAGIC;
+        mapping.ver = VERSION_2;
+        mapping.atype = AccountType::Mapping as u32;
+        mapping.num = 1;
+        mapping.products[0].val = btc_key.to_bytes();
+        mapping.next.val = next_mapping_key.to_bytes();
+        accounts.insert(mapping_key, bytes_of(&mapping).to_vec());
+        mapping.products[0].val = sol_key.to_bytes();
+        mapping.next = AccKey { val: [0; 32] };
+        accounts.insert(next_mapping_key, bytes_of(&mapping).to_vec());
+
+        for (key, symbol) in [(btc_key, "Crypto.BTC/USD"), (sol_key, "Crypto.SOL/USD")] {
+            let mut product = product_account(&[("symbol", symbol)]);
+            product.magic = MAGIC;
+            product.ver = VERSION_2;
+            product.atype = AccountType::Product as u32;
+            product.px_acc.val = twap_key.to_bytes();
+            accounts.insert(key, bytes_of(&product).to_vec());
+        }
+
+        let mut price = price_account();
+        price.ptype = PriceType::Unknown;
+        price.next.val = price_key.to_bytes();
+        accounts.insert(twap_key, bytes_of(&price).to_vec());
+        price.ptype = PriceType::Price;
+        price.next = AccKey { val: [0; 32] };
+        accounts.insert(price_key, bytes_of(&price).to_vec());
+
+        let get_account_data = |key: &Pubkey| accounts.get(key).cloned();
+        assert_eq!(
+            find_price_feed(&mapping_key, "Crypto.SOL/USD", get_account_data),
+            Ok(Some(PriceFeed {
+                product: sol_key,
+                price: price_key,
+            }))
+        );
+        assert_eq!(
+            find_price_feed(&mapping_key, "Crypto.ETH/USD", get_account_data),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn load_price_account_checks_header() {
+        let price = price_account();
+        assert!(load_price_account(bytes_of(&price)).is_ok());
+
+        let data = bytes_of(&price);
+        assert_eq!(
+            load_price_account(&data[..data.len() - 1]).err(),
+            Some(LendingError::InvalidOracleConfig.into())
+        );
+        assert_eq!(
+            load_product_account(data).err(),
+            Some(LendingError::InvalidOracleConfig.into())
+        );
+
+        let mut wrong_magic = price;
+        wrong_magic.magic = 0;
+        assert_eq!(
+            load_price_account(bytes_of(&wrong_magic)).err(),
+            Some(LendingError::InvalidOracleConfig.into())
+        );
+
+        let mut wrong_version = price;
+        wrong_version.ver = 1;
+        assert_eq!(
+            load_price_account(bytes_of(&wrong_version)).err(),
+            Some(LendingError::InvalidOracleConfig.into())
+        );
+    }
 }